@@ -2,7 +2,7 @@ use csv;
 #[cfg(feature = "gen-fake")]
 use fake::vec;
 
-use adverse_events::AdverseEventRecord;
+use adverse_events::{AdverseEventRecord, RECORD_COLUMNS};
 
 use std::{env, io};
 
@@ -18,27 +18,7 @@ fn main() {
         .has_headers(false)
         .from_writer(io::stdout());
 
-    writer
-        .write_record(&[
-            "Date",
-            "MRN",
-            "Episode ID",
-            "Patient Name",
-            "Diagnosis",
-            "Procedure",
-            "Anesthesiologist",
-            "Anesthesia Staff",
-            "Location",
-            "Anesthesia Complications",
-            "Adverse Events",
-            "ASA",
-            "An Start",
-            "An Stop",
-            "Smoker?",
-            "Age (Years)",
-            "BMI",
-        ])
-        .unwrap();
+    writer.write_record(&RECORD_COLUMNS).unwrap();
 
     for record in records {
         writer.serialize(record).unwrap();
@@ -0,0 +1,408 @@
+use super::*;
+
+use std::str::FromStr;
+
+// Width binning stops after this many bins, with anything above them in a
+// final open-ended bin, so a tiny width can't create millions of bins
+pub const MAX_BINS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumericField {
+    Age,
+    Bmi,
+    Asa,
+    DurationHours,
+    ConcurrentCases,
+    FacilityConcurrentCases,
+    // Numeric column joined from a supplementary extract
+    Attribute(String),
+}
+
+impl NumericField {
+    pub fn value(&self, record: &AdverseEventRecord) -> Option<f64> {
+        let value = match self {
+            NumericField::Age => record.age as f64,
+            NumericField::Bmi => record.bmi,
+            NumericField::Asa => record.asa as f64,
+            NumericField::DurationHours => record.anesthesia_hours(),
+            NumericField::ConcurrentCases => record.concurrency.anesthesiologist_cases as f64,
+            NumericField::FacilityConcurrentCases => record.concurrency.facility_cases as f64,
+            NumericField::Attribute(name) => match record.attributes.get(name) {
+                Some(AttributeValue::Number(value)) => *value,
+                _ => return None,
+            },
+        };
+
+        if value.is_finite() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn is_integral(&self) -> bool {
+        match self {
+            NumericField::Age
+            | NumericField::Asa
+            | NumericField::ConcurrentCases
+            | NumericField::FacilityConcurrentCases => true,
+            NumericField::Bmi | NumericField::DurationHours | NumericField::Attribute(_) => false,
+        }
+    }
+}
+
+impl FromStr for NumericField {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "age" => Ok(NumericField::Age),
+            "bmi" => Ok(NumericField::Bmi),
+            "asa" => Ok(NumericField::Asa),
+            "duration" => Ok(NumericField::DurationHours),
+            "concurrentCases" => Ok(NumericField::ConcurrentCases),
+            "facilityConcurrentCases" => Ok(NumericField::FacilityConcurrentCases),
+            x => match x.strip_prefix("attribute:") {
+                Some(name) if !name.is_empty() => Ok(NumericField::Attribute(name.to_string())),
+                _ => Err(crate::Error::ParseError {
+                    type_name: "NumericField",
+                    received: x.to_string(),
+                }),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binning {
+    Width(f64),
+    Edges(Vec<f64>),
+    Quantiles(usize),
+    AgeCategories,
+    BmiCategories,
+}
+
+impl FromStr for Binning {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || crate::Error::ParseError {
+            type_name: "Binning",
+            received: s.to_string(),
+        };
+
+        let (kind, arg) = match s.find(':') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+
+        match (kind, arg) {
+            ("width", Some(width)) => match width.parse::<f64>() {
+                Ok(width) if width > 0.0 && width.is_finite() => Ok(Binning::Width(width)),
+                _ => Err(err()),
+            },
+            ("edges", Some(edges)) => {
+                let mut edges = edges
+                    .split(',')
+                    .map(|edge| edge.trim().parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| err())?;
+                if edges.is_empty() || edges.iter().any(|edge| !edge.is_finite()) {
+                    return Err(err());
+                }
+                edges.sort_by(f64::total_cmp);
+                edges.dedup();
+                Ok(Binning::Edges(edges))
+            }
+            ("quantiles", Some(count)) => match count.parse::<usize>() {
+                Ok(count) if count > 0 => Ok(Binning::Quantiles(count)),
+                _ => Err(err()),
+            },
+            ("ageCategories", None) => Ok(Binning::AgeCategories),
+            ("bmiCategories", None) => Ok(Binning::BmiCategories),
+            _ => Err(err()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct Bin {
+    pub label: String,
+    // Bins are half-open, [lower, upper)
+    pub lower: Option<f64>,
+    pub upper: Option<f64>,
+    #[serde(skip)]
+    tail: bool,
+}
+
+impl Bin {
+    pub fn contains(&self, value: f64) -> bool {
+        !matches!(self.lower, Some(lower) if value < lower)
+            && !matches!(self.upper, Some(upper) if value >= upper)
+    }
+
+    fn from_bounds(field: &NumericField, lower: Option<f64>, upper: Option<f64>) -> Self {
+        let label = match (lower, upper) {
+            (Some(lower), Some(upper)) => {
+                if field.is_integral() && lower.fract() == 0.0 && upper.fract() == 0.0 {
+                    format!("{} - {}", lower, upper - 1.0)
+                } else {
+                    format!("{} - <{}", lower, upper)
+                }
+            }
+            (Some(lower), None) => format!("{}+", lower),
+            (None, Some(upper)) => format!("< {}", upper),
+            (None, None) => "All".to_string(),
+        };
+
+        Bin {
+            label,
+            lower,
+            upper,
+            tail: lower.is_none() || upper.is_none(),
+        }
+    }
+
+    fn category(label: &str, lower: Option<f64>, upper: Option<f64>) -> Self {
+        Bin {
+            label: label.to_string(),
+            lower,
+            upper,
+            tail: false,
+        }
+    }
+}
+
+impl Binning {
+    pub fn bins(&self, field: &NumericField, values: &[f64]) -> Vec<Bin> {
+        let values = values.iter().copied().filter(|value| value.is_finite());
+
+        match self {
+            Binning::Width(width) => {
+                let min = values.clone().fold(f64::INFINITY, f64::min);
+                let max = values.fold(f64::NEG_INFINITY, f64::max);
+                if !min.is_finite() || !max.is_finite() {
+                    return Vec::new();
+                }
+
+                let first = (min / width).floor();
+                let last = (max / width).floor();
+                let count = (last - first + 1.0).min(MAX_BINS as f64) as usize;
+
+                let mut bins: Vec<Bin> = (0..count)
+                    .map(|i| {
+                        let i = first + i as f64;
+                        Bin::from_bounds(field, Some(i * width), Some((i + 1.0) * width))
+                    })
+                    .collect();
+                if count as f64 <= last - first {
+                    bins.push(Bin::from_bounds(
+                        field,
+                        Some((first + count as f64) * width),
+                        None,
+                    ));
+                }
+
+                bins
+            }
+            Binning::Edges(edges) => Self::bins_from_edges(field, edges, true),
+            Binning::Quantiles(count) => {
+                let mut sorted: Vec<f64> = values.collect();
+                sorted.sort_by(f64::total_cmp);
+                if sorted.is_empty() {
+                    return Vec::new();
+                }
+
+                let mut edges: Vec<f64> = (0..*count)
+                    .map(|i| sorted[i * sorted.len() / count])
+                    .collect();
+                edges.dedup();
+
+                Self::bins_from_edges(field, &edges, false)
+            }
+            // Ages are recorded in whole years, so neonates can't be told
+            // apart from other infants
+            Binning::AgeCategories => vec![
+                Bin::category("Neonate/infant", None, Some(1.0)),
+                Bin::category("Child", Some(1.0), Some(18.0)),
+                Bin::category("Adult", Some(18.0), Some(65.0)),
+                Bin::category("Geriatric", Some(65.0), None),
+            ],
+            Binning::BmiCategories => vec![
+                Bin::category("Underweight", None, Some(18.5)),
+                Bin::category("Normal weight", Some(18.5), Some(25.0)),
+                Bin::category("Overweight", Some(25.0), Some(30.0)),
+                Bin::category("Obesity class I", Some(30.0), Some(35.0)),
+                Bin::category("Obesity class II", Some(35.0), Some(40.0)),
+                Bin::category("Obesity class III", Some(40.0), None),
+            ],
+        }
+    }
+
+    fn bins_from_edges(field: &NumericField, edges: &[f64], lower_tail: bool) -> Vec<Bin> {
+        let mut bins = Vec::new();
+
+        if let Some(first) = edges.first() {
+            if lower_tail {
+                bins.push(Bin::from_bounds(field, None, Some(*first)));
+            }
+        }
+
+        for pair in edges.windows(2) {
+            bins.push(Bin::from_bounds(field, Some(pair[0]), Some(pair[1])));
+        }
+
+        if let Some(last) = edges.last() {
+            bins.push(Bin::from_bounds(field, Some(*last), None));
+        }
+
+        bins
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct HistogramBin {
+    #[serde(flatten)]
+    pub bin: Bin,
+    pub total: usize,
+    pub with_outcome: usize,
+    pub rate: f64,
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn binned(
+        &self,
+        field: &NumericField,
+        binning: &Binning,
+    ) -> Vec<(Bin, AdverseEventsView<'a>)> {
+        let values: Vec<f64> = self
+            .records
+            .iter()
+            .filter_map(|record| field.value(record))
+            .collect();
+
+        let mut bins: Vec<(Bin, AdverseEventsView<'a>)> = binning
+            .bins(field, &values)
+            .into_iter()
+            .map(|bin| (bin, AdverseEventsView::empty()))
+            .collect();
+
+        for record in &self.records {
+            if let Some(value) = field.value(record) {
+                if let Some((_, view)) = bins.iter_mut().find(|(bin, _)| bin.contains(value)) {
+                    view.records.push(*record);
+                }
+            }
+        }
+
+        // Open-ended tails are only reported when something falls in them
+        bins.into_iter()
+            .filter(|(bin, view)| !bin.tail || !view.records.is_empty())
+            .collect()
+    }
+
+    pub fn histogram(
+        &self,
+        field: &NumericField,
+        binning: &Binning,
+        outcome: &Outcome,
+    ) -> Vec<HistogramBin> {
+        self.binned(field, binning)
+            .into_iter()
+            .map(|(bin, view)| {
+                let total = view.len();
+                let with_outcome = view.count_outcome(outcome);
+
+                HistogramBin {
+                    bin,
+                    total,
+                    with_outcome,
+                    rate: if total == 0 {
+                        0.0
+                    } else {
+                        with_outcome as f64 / total as f64
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    fn labels(bins: &[Bin]) -> Vec<&str> {
+        bins.iter().map(|bin| bin.label.as_str()).collect()
+    }
+
+    #[test]
+    fn binning_from_str() {
+        assert_eq!("width:10".parse::<Binning>().unwrap(), Binning::Width(10.0));
+        assert_eq!(
+            "edges:30, 18,30".parse::<Binning>().unwrap(),
+            Binning::Edges(vec![18.0, 30.0])
+        );
+        assert_eq!(
+            "bmiCategories".parse::<Binning>().unwrap(),
+            Binning::BmiCategories
+        );
+        for invalid in [
+            "width:0",
+            "width",
+            "edges:",
+            "quantiles:0",
+            "ageCategories:2",
+        ] {
+            assert!(invalid.parse::<Binning>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn width_bins_are_labelled_by_field() {
+        let ages = Binning::Width(10.0).bins(&NumericField::Age, &[5.0, 15.0, 27.0]);
+        assert_eq!(labels(&ages), ["0 - 9", "10 - 19", "20 - 29"]);
+
+        let bmis = Binning::Width(5.0).bins(&NumericField::Bmi, &[22.0, 26.0]);
+        assert_eq!(labels(&bmis), ["20 - <25", "25 - <30"]);
+        assert!(bmis[0].contains(20.0) && !bmis[0].contains(25.0));
+    }
+
+    #[test]
+    fn width_bins_are_capped() {
+        let bins = Binning::Width(1.0).bins(&NumericField::Bmi, &[0.0, 5000.0]);
+        assert_eq!(bins.len(), MAX_BINS + 1);
+        assert_eq!(bins.last().unwrap().label, "1000+");
+        assert!(bins.last().unwrap().contains(5000.0));
+    }
+
+    #[test]
+    fn histogram_leaves_out_empty_tails() {
+        let ages = [(10, true), (30, false), (70, true), (70, false)];
+        let adverse_events =
+            test_data::adverse_events(ages.iter().map(|&(age, event)| TestRecord {
+                age,
+                ..TestRecord::with_event(event)
+            }));
+        let edges = Binning::Edges(vec![18.0, 65.0]);
+
+        let histogram =
+            adverse_events
+                .view()
+                .histogram(&NumericField::Age, &edges, &Outcome::AnyEvent);
+        let bins: Vec<(&str, usize, usize)> = histogram
+            .iter()
+            .map(|bin| (bin.bin.label.as_str(), bin.total, bin.with_outcome))
+            .collect();
+        assert_eq!(bins, [("< 18", 1, 1), ("18 - 64", 1, 0), ("65+", 2, 1)]);
+        assert_eq!(histogram[2].rate, 0.5);
+
+        let adults = adverse_events.view().with_filter(|record| record.age == 30);
+        let binned = adults.binned(&NumericField::Age, &edges);
+        assert_eq!(binned.len(), 1);
+        assert_eq!(binned[0].0.label, "18 - 64");
+    }
+}
@@ -8,6 +8,47 @@ pub struct LabeledCount {
     pub value: usize,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct LabeledRate {
+    pub label: String,
+    pub total: usize,
+    pub with_outcome: usize,
+    pub rate: f64,
+    pub ci: ConfidenceInterval,
+}
+
+impl LabeledRate {
+    pub fn new(label: String, view: &AdverseEventsView<'_>, outcome: &Outcome) -> Self {
+        Self::from_counts(label, view.len(), view.count_outcome(outcome))
+    }
+
+    pub fn from_counts(label: String, total: usize, with_outcome: usize) -> Self {
+        LabeledRate {
+            label,
+            total,
+            with_outcome,
+            rate: if total == 0 {
+                0.0
+            } else {
+                with_outcome as f64 / total as f64
+            },
+            // An external denominator can disagree with the records, so keep
+            // the interval defined
+            ci: wilson_ci(with_outcome.min(total), total, Z_95),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct Stratum<T: Serialize> {
+    pub label: String,
+    pub total: usize,
+    pub breakdown: Vec<T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BreakdownType {
     WithComplications,
     WithEvent,
@@ -33,3 +74,252 @@ impl FromStr for BreakdownType {
         }
     }
 }
+
+impl<'a> AdverseEventsView<'a> {
+    /// Splits every record in the view into the categories of a breakdown,
+    /// regardless of whether they had an event.
+    pub fn breakdown_groups(&self, breakdown_type: BreakdownType) -> Vec<DimensionGroup<'a>> {
+        match breakdown_type {
+            BreakdownType::WithComplications => vec![
+                DimensionGroup {
+                    label: "With complications".to_string(),
+                    value: self.with_filter(|record| record.complications == Some(true)),
+                },
+                DimensionGroup {
+                    label: "Without complications".to_string(),
+                    value: self.with_filter(|record| record.complications == Some(false)),
+                },
+                DimensionGroup {
+                    label: "Unspecified complications".to_string(),
+                    value: self.with_filter(|record| record.complications.is_none()),
+                },
+            ],
+            BreakdownType::WithEvent => vec![
+                DimensionGroup {
+                    label: "With event".to_string(),
+                    value: self.with_filter(|record| !record.adverse_events.is_empty()),
+                },
+                DimensionGroup {
+                    label: "Without event".to_string(),
+                    value: self.with_filter(|record| record.adverse_events.is_empty()),
+                },
+            ],
+            BreakdownType::PatientAge => {
+                self.group_by_dimension(&Dimension::Binned(NumericField::Age, Binning::Width(10.0)))
+            }
+            BreakdownType::PatientBmi => {
+                self.group_by_dimension(&Dimension::Binned(NumericField::Bmi, Binning::Width(5.0)))
+            }
+            BreakdownType::PatientSmoker => self.group_by_dimension(&Dimension::Smoker),
+        }
+    }
+
+    pub fn get_breakdown_rates(
+        &self,
+        breakdown_type: BreakdownType,
+        outcome: &Outcome,
+    ) -> Vec<LabeledRate> {
+        self.breakdown_groups(breakdown_type)
+            .into_iter()
+            .map(|group| LabeledRate::new(group.label, &group.value, outcome))
+            .collect()
+    }
+
+    /// Splits the view by `stratify_by`, breaking each stratum down into the
+    /// same categories so strata line up for grouped or stacked charts.
+    pub fn stratified_breakdown_groups(
+        &self,
+        breakdown_type: BreakdownType,
+        stratify_by: Option<&Dimension>,
+    ) -> Vec<Stratum<DimensionGroup<'a>>> {
+        let labels: Vec<String> = self
+            .breakdown_groups(breakdown_type)
+            .into_iter()
+            .map(|group| group.label)
+            .collect();
+
+        let strata = match stratify_by {
+            Some(dimension) => self.group_by_dimension(dimension),
+            None => vec![DimensionGroup {
+                label: "All".to_string(),
+                value: AdverseEventsView {
+                    records: self.records.clone(),
+                },
+            }],
+        };
+
+        strata
+            .into_iter()
+            .map(|stratum| {
+                // Categories missing from a stratum are left empty, and
+                // binned categories are labeled the same in every stratum
+                let mut groups = stratum.value.breakdown_groups(breakdown_type);
+                let breakdown = labels
+                    .iter()
+                    .map(|label| DimensionGroup {
+                        label: label.clone(),
+                        value: groups
+                            .iter()
+                            .position(|group| &group.label == label)
+                            .map(|i| groups.swap_remove(i).value)
+                            .unwrap_or_else(AdverseEventsView::empty),
+                    })
+                    .collect();
+
+                Stratum {
+                    label: stratum.label,
+                    total: stratum.value.len(),
+                    breakdown,
+                }
+            })
+            .collect()
+    }
+
+    /// Counts for each category of the breakdown, within each stratum of
+    /// `stratify_by` or in a single "All" stratum without one. Patient
+    /// characteristics only count records with an event.
+    pub fn get_breakdown(
+        &self,
+        breakdown_type: BreakdownType,
+        stratify_by: Option<&Dimension>,
+    ) -> Vec<Stratum<LabeledCount>> {
+        let events_only = !matches!(
+            breakdown_type,
+            BreakdownType::WithComplications | BreakdownType::WithEvent
+        );
+
+        self.stratified_breakdown_groups(breakdown_type, stratify_by)
+            .into_iter()
+            .map(|stratum| Stratum {
+                label: stratum.label,
+                total: stratum.total,
+                breakdown: stratum
+                    .breakdown
+                    .into_iter()
+                    .map(|group| LabeledCount {
+                        label: group.label,
+                        value: if events_only {
+                            group
+                                .value
+                                .count(|record| !record.adverse_events.is_empty())
+                        } else {
+                            group.value.len()
+                        },
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub fn get_stratified_breakdown_rates(
+        &self,
+        breakdown_type: BreakdownType,
+        stratify_by: Option<&Dimension>,
+        outcome: &Outcome,
+    ) -> Vec<Stratum<LabeledRate>> {
+        self.stratified_breakdown_groups(breakdown_type, stratify_by)
+            .into_iter()
+            .map(|stratum| Stratum {
+                label: stratum.label,
+                total: stratum.total,
+                breakdown: stratum
+                    .breakdown
+                    .into_iter()
+                    .map(|group| LabeledRate::new(group.label, &group.value, outcome))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    #[test]
+    fn breakdown_rates_count_records_without_events() {
+        // (smoker, with an event, records)
+        let groups = [
+            (true, true, 2),
+            (true, false, 2),
+            (false, true, 1),
+            (false, false, 5),
+        ];
+        let adverse_events =
+            test_data::adverse_events(groups.iter().flat_map(|&(smoker, event, records)| {
+                (0..records).map(move |_| TestRecord {
+                    smoker,
+                    ..TestRecord::with_event(event)
+                })
+            }));
+
+        let rates = adverse_events
+            .view()
+            .get_breakdown_rates(BreakdownType::PatientSmoker, &Outcome::AnyEvent);
+        let rate = |label: &str| rates.iter().find(|rate| rate.label == label).unwrap();
+        assert_eq!((rate("Smoker").total, rate("Smoker").with_outcome), (4, 2));
+        assert_eq!(rate("Smoker").rate, 0.5);
+        assert_eq!(
+            (rate("Non-smoker").total, rate("Non-smoker").with_outcome),
+            (6, 1)
+        );
+        let ci = &rate("Non-smoker").ci;
+        assert!(ci.lower < 1.0 / 6.0 && ci.upper > 1.0 / 6.0);
+    }
+
+    #[test]
+    fn strata_share_breakdown_categories() {
+        // (location, BMI, with an event)
+        let records = [
+            ("A", 22.0, true),
+            ("A", 27.0, true),
+            ("A", 27.0, false),
+            ("B", 22.0, true),
+            ("B", 23.0, false),
+        ];
+        let adverse_events =
+            test_data::adverse_events(records.iter().map(|&(location, bmi, event)| TestRecord {
+                location,
+                bmi,
+                ..TestRecord::with_event(event)
+            }));
+        let view = adverse_events.view();
+
+        let strata = view.get_breakdown(BreakdownType::PatientBmi, Some(&Dimension::Location));
+        let totals: Vec<(&str, usize)> = strata
+            .iter()
+            .map(|stratum| (stratum.label.as_str(), stratum.total))
+            .collect();
+        assert_eq!(totals, [("A", 3), ("B", 2)]);
+        // Patient characteristics count records with an event, and B has
+        // none in the upper bin but still lists it
+        for (stratum, expected) in strata.iter().zip([[1, 1], [1, 0]]) {
+            let labels: Vec<&str> = stratum
+                .breakdown
+                .iter()
+                .map(|count| count.label.as_str())
+                .collect();
+            let counts: Vec<usize> = stratum.breakdown.iter().map(|count| count.value).collect();
+            assert_eq!(labels, ["20 - <25", "25 - <30"]);
+            assert_eq!(counts, expected);
+        }
+
+        let all =
+            view.get_stratified_breakdown_rates(BreakdownType::WithEvent, None, &Outcome::AnyEvent);
+        assert_eq!(all.len(), 1);
+        assert_eq!((all[0].label.as_str(), all[0].total), ("All", 5));
+        assert_eq!(all[0].breakdown[0].rate, 1.0);
+        assert_eq!(all[0].breakdown[1].rate, 0.0);
+    }
+
+    #[test]
+    fn rates_from_counts_stay_defined() {
+        let empty = LabeledRate::from_counts("Empty".to_string(), 0, 0);
+        assert_eq!(empty.rate, 0.0);
+
+        // A denominator smaller than the events it's meant to cover
+        let over = LabeledRate::from_counts("Over".to_string(), 2, 3);
+        assert!(over.ci.lower.is_finite() && over.ci.upper <= 1.0);
+    }
+}
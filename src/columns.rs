@@ -0,0 +1,271 @@
+use super::*;
+
+use csv::StringRecord;
+
+use std::io::Write;
+
+/// Columns read into `AdverseEventRecord` fields, in export order.
+pub const RECORD_COLUMNS: [&str; 17] = [
+    "Date",
+    "MRN",
+    "Episode ID",
+    "Patient Name",
+    "Diagnosis",
+    "Procedure",
+    "Anesthesiologist",
+    "Anesthesia Staff",
+    "Location",
+    "Anesthesia Complications",
+    "Adverse Events",
+    "ASA",
+    "An Start",
+    "An Stop",
+    "Smoker?",
+    "Age (Years)",
+    "BMI",
+];
+
+impl AttributeType {
+    /// The narrowest type every non-blank value can be read as.
+    pub fn infer<'v>(values: impl Iterator<Item = &'v str> + Clone) -> Self {
+        [
+            AttributeType::Number,
+            AttributeType::Bool,
+            AttributeType::Date,
+        ]
+        .iter()
+        .copied()
+        .find(|attribute_type| {
+            values.clone().all(|value| {
+                AttributeValue::parse(*attribute_type, value).is_ok()
+                    && !(*attribute_type == AttributeType::Number && has_leading_zero(value))
+            })
+        })
+        .unwrap_or(AttributeType::Text)
+    }
+}
+
+// Codes like "01234" would lose their leading zeros if read as numbers
+fn has_leading_zero(value: &str) -> bool {
+    let digits = value.trim().trim_start_matches(&['-', '+'][..]).as_bytes();
+    digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit()
+}
+
+/// Keeps columns the record struct doesn't know about as attributes, typed
+/// by what the column's values look like.
+pub(crate) fn capture_extra_columns(
+    headers: &StringRecord,
+    rows: &[StringRecord],
+    records: &mut [AdverseEventRecord],
+) {
+    for (i, header) in headers.iter().enumerate() {
+        let name = header.trim();
+        if name.is_empty() || RECORD_COLUMNS.contains(&name) {
+            continue;
+        }
+
+        let attribute_type = AttributeType::infer(rows.iter().map(|row| row.get(i).unwrap_or("")));
+
+        for (row, record) in rows.iter().zip(records.iter_mut()) {
+            if let Ok(Some(value)) = AttributeValue::parse(attribute_type, row.get(i).unwrap_or(""))
+            {
+                record.attributes.insert(name.to_string(), value);
+            }
+        }
+    }
+}
+
+/// Formats a field with the serializer its column is read back with.
+fn serialized<T>(
+    serialize: fn(
+        &T,
+        serde_json::value::Serializer,
+    ) -> Result<serde_json::Value, serde_json::Error>,
+    value: &T,
+) -> String {
+    match serialize(value, serde_json::value::Serializer) {
+        Ok(serde_json::Value::String(text)) => text,
+        Ok(other) => other.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+impl AdverseEventRecord {
+    fn csv_row(&self, attribute_names: &[&str]) -> Vec<String> {
+        let mut row = vec![
+            serialized(mm_dd_yy_date::serialize, &self.date),
+            self.mrn.clone(),
+            self.episode_id.clone(),
+            self.patient_name.clone(),
+            self.diagnosis.clone(),
+            self.procedure.clone(),
+            self.anesthesiologist.clone(),
+            serialized(line_separated::serialize, &self.anesthesia_staff),
+            self.location.clone(),
+            serialized(nullable_yes_no_bool::serialize, &self.complications),
+            serialized(comma_separated::serialize, &self.adverse_events),
+            self.asa.to_string(),
+            serialized(hhmm_time::serialize, &self.an_start),
+            serialized(hhmm_time::serialize, &self.an_stop),
+            serialized(non_null_bool::serialize, &self.smoker),
+            self.age.to_string(),
+            self.bmi.to_string(),
+        ];
+
+        row.extend(attribute_names.iter().map(|name| {
+            self.attributes
+                .get(*name)
+                .map(|value| value.to_string())
+                .unwrap_or_default()
+        }));
+
+        row
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    /// Names of all attributes present on any record, sorted.
+    pub fn attribute_names(&self) -> Vec<&'a str> {
+        let mut names: Vec<&'a str> = self
+            .records
+            .iter()
+            .flat_map(|record| record.attributes.keys().map(String::as_str))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Number of records with each value of an attribute.
+    pub fn attribute_counts(&self, name: &str) -> Vec<LabeledCount> {
+        self.group_by_dimension(&Dimension::Attribute(name.to_string()))
+            .into_iter()
+            .map(|group| LabeledCount {
+                label: group.label,
+                value: group.value.len(),
+            })
+            .collect()
+    }
+
+    /// Writes records in the same layout they're loaded from, with any
+    /// attributes as additional columns.
+    pub fn write_csv<W>(&self, writer: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        self.write_redacted_csv(writer, VisibilityProfile::FullPhi, &BTreeSet::new())
+    }
+
+    /// Like `write_csv`, leaving out columns the profile can't view.
+    pub fn write_redacted_csv<W>(
+        &self,
+        writer: W,
+        profile: VisibilityProfile,
+        allowed_attributes: &BTreeSet<String>,
+    ) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let table = self.redacted_table(profile, allowed_attributes)?;
+        let mut writer = csv::Writer::from_writer(writer);
+
+        writer.write_record(&table.headers)?;
+        for row in &table.rows {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Records in their export layout as a table of the columns the
+    /// profile can view.
+    pub fn redacted_table(
+        &self,
+        profile: VisibilityProfile,
+        allowed_attributes: &BTreeSet<String>,
+    ) -> Result<Table, Error> {
+        profile.check_records()?;
+
+        let attribute_names: Vec<&str> = self
+            .attribute_names()
+            .into_iter()
+            .filter(|name| profile.can_use_attribute(name, allowed_attributes))
+            .collect();
+        let visible: Vec<bool> = RecordField::ALL
+            .iter()
+            .filter(|field| field.column().is_some())
+            .map(|field| profile.can_view(*field))
+            .chain(attribute_names.iter().map(|_| true))
+            .collect();
+
+        let mut table = Table::new(
+            RECORD_COLUMNS
+                .iter()
+                .chain(attribute_names.iter())
+                .zip(&visible)
+                .filter(|(_, visible)| **visible)
+                .map(|(column, _)| column),
+        );
+        for record in &self.records {
+            table.push(
+                record
+                    .csv_row(&attribute_names)
+                    .into_iter()
+                    .zip(&visible)
+                    .filter(|(_, visible)| **visible)
+                    .map(|(value, _)| value),
+            );
+        }
+
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    #[test]
+    fn infer_keeps_leading_zeros_as_text() {
+        assert_eq!(
+            AttributeType::infer(["12", "0.5", "-3"].iter().copied()),
+            AttributeType::Number
+        );
+        assert_eq!(
+            AttributeType::infer(["01234", "10001"].iter().copied()),
+            AttributeType::Text
+        );
+    }
+
+    #[test]
+    fn extra_columns_round_trip() {
+        let adverse_events = test_data::adverse_events(vec![
+            TestRecord {
+                extra: vec![("Zip", "01234"), ("Score", "1.5")],
+                ..Default::default()
+            },
+            TestRecord {
+                extra: vec![("Zip", "10001"), ("Score", "2")],
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(
+            adverse_events.records[0].attributes.get("Score"),
+            Some(&AttributeValue::Number(1.5))
+        );
+
+        let mut exported = Vec::new();
+        adverse_events.view().write_csv(&mut exported).unwrap();
+        let reloaded = AdverseEvents::from_csv_reader(exported.as_slice()).unwrap();
+        assert_eq!(
+            reloaded.records[0].attributes.get("Zip"),
+            Some(&AttributeValue::Text("01234".to_string()))
+        );
+        assert_eq!(
+            reloaded.records[0].attributes,
+            adverse_events.records[0].attributes
+        );
+    }
+}
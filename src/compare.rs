@@ -0,0 +1,229 @@
+use super::*;
+
+// Fisher's exact test is used instead of chi-square when any expected cell
+// count falls below this
+const MIN_EXPECTED_CELL_COUNT: f64 = 5.0;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct TwoByTwoTable {
+    pub a_with_outcome: usize,
+    pub a_without_outcome: usize,
+    pub b_with_outcome: usize,
+    pub b_without_outcome: usize,
+}
+
+impl TwoByTwoTable {
+    pub fn a_total(&self) -> usize {
+        self.a_with_outcome + self.a_without_outcome
+    }
+
+    pub fn b_total(&self) -> usize {
+        self.b_with_outcome + self.b_without_outcome
+    }
+
+    pub fn total(&self) -> usize {
+        self.a_total() + self.b_total()
+    }
+
+    fn expected_counts(&self) -> [f64; 4] {
+        let total = self.total() as f64;
+        let with_outcome = (self.a_with_outcome + self.b_with_outcome) as f64;
+        let without_outcome = (self.a_without_outcome + self.b_without_outcome) as f64;
+        let a = self.a_total() as f64;
+        let b = self.b_total() as f64;
+
+        [
+            a * with_outcome / total,
+            a * without_outcome / total,
+            b * with_outcome / total,
+            b * without_outcome / total,
+        ]
+    }
+
+    fn observed_counts(&self) -> [f64; 4] {
+        [
+            self.a_with_outcome as f64,
+            self.a_without_outcome as f64,
+            self.b_with_outcome as f64,
+            self.b_without_outcome as f64,
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum SignificanceTest {
+    ChiSquare,
+    FisherExact,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct Estimate {
+    pub value: f64,
+    pub ci: ConfidenceInterval,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct Comparison {
+    pub outcome: Outcome,
+    pub table: TwoByTwoTable,
+    pub risk_a: f64,
+    pub risk_b: f64,
+    pub risk_difference: Estimate,
+    pub relative_risk: Estimate,
+    pub odds_ratio: Estimate,
+    pub test: SignificanceTest,
+    pub chi_square: Option<f64>,
+    pub p_value: f64,
+}
+
+pub fn compare(
+    view_a: &AdverseEventsView<'_>,
+    view_b: &AdverseEventsView<'_>,
+    outcome: Outcome,
+) -> Comparison {
+    let a_with_outcome = view_a.count_outcome(&outcome);
+    let b_with_outcome = view_b.count_outcome(&outcome);
+    let table = TwoByTwoTable {
+        a_with_outcome,
+        a_without_outcome: view_a.len() - a_with_outcome,
+        b_with_outcome,
+        b_without_outcome: view_b.len() - b_with_outcome,
+    };
+
+    let a_total = table.a_total() as f64;
+    let b_total = table.b_total() as f64;
+    let risk_a = table.a_with_outcome as f64 / a_total;
+    let risk_b = table.b_with_outcome as f64 / b_total;
+
+    let difference_se =
+        (risk_a * (1.0 - risk_a) / a_total + risk_b * (1.0 - risk_b) / b_total).sqrt();
+    let risk_difference = Estimate {
+        value: risk_a - risk_b,
+        ci: ConfidenceInterval {
+            lower: risk_a - risk_b - Z_95 * difference_se,
+            upper: risk_a - risk_b + Z_95 * difference_se,
+        },
+    };
+
+    // Haldane-Anscombe correction so ratios stay finite with an empty cell
+    let [a, b, c, d] = if table.observed_counts().contains(&0.0) {
+        let [a, b, c, d] = table.observed_counts();
+        [a + 0.5, b + 0.5, c + 0.5, d + 0.5]
+    } else {
+        table.observed_counts()
+    };
+
+    let relative_risk = log_estimate(
+        (a / (a + b)) / (c / (c + d)),
+        (1.0 / a - 1.0 / (a + b) + 1.0 / c - 1.0 / (c + d)).sqrt(),
+    );
+    let odds_ratio = log_estimate(
+        (a * d) / (b * c),
+        (1.0 / a + 1.0 / b + 1.0 / c + 1.0 / d).sqrt(),
+    );
+
+    let expected = table.expected_counts();
+    let (test, chi_square, p_value) = if expected
+        .iter()
+        .any(|count| count.is_nan() || *count < MIN_EXPECTED_CELL_COUNT)
+    {
+        (
+            SignificanceTest::FisherExact,
+            None,
+            fisher_exact_p_value(
+                table.a_with_outcome,
+                table.a_without_outcome,
+                table.b_with_outcome,
+                table.b_without_outcome,
+            ),
+        )
+    } else {
+        let statistic: f64 = table
+            .observed_counts()
+            .iter()
+            .zip(expected.iter())
+            .map(|(observed, expected)| (observed - expected).powi(2) / expected)
+            .sum();
+
+        (
+            SignificanceTest::ChiSquare,
+            Some(statistic),
+            chi_square_p_value(statistic),
+        )
+    };
+
+    Comparison {
+        outcome,
+        table,
+        risk_a,
+        risk_b,
+        risk_difference,
+        relative_risk,
+        odds_ratio,
+        test,
+        chi_square,
+        p_value,
+    }
+}
+
+fn log_estimate(value: f64, log_se: f64) -> Estimate {
+    Estimate {
+        value,
+        ci: ConfidenceInterval {
+            lower: (value.ln() - Z_95 * log_se).exp(),
+            upper: (value.ln() + Z_95 * log_se).exp(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    fn adverse_events(with_outcome: usize, without_outcome: usize) -> AdverseEvents {
+        test_data::adverse_events(
+            (0..with_outcome + without_outcome).map(|i| TestRecord::with_event(i < with_outcome)),
+        )
+    }
+
+    #[test]
+    fn small_tables_use_fisher() {
+        let a = adverse_events(3, 1);
+        let b = adverse_events(1, 3);
+        let comparison = compare(&a.view(), &b.view(), Outcome::AnyEvent);
+
+        assert_eq!(comparison.test, SignificanceTest::FisherExact);
+        assert!(comparison.chi_square.is_none());
+        assert!((comparison.p_value - 0.485_714).abs() < 1e-6);
+        assert!((comparison.risk_difference.value - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn large_tables_use_chi_square() {
+        let a = adverse_events(30, 70);
+        let b = adverse_events(15, 85);
+        let comparison = compare(&a.view(), &b.view(), Outcome::AnyEvent);
+
+        assert_eq!(comparison.test, SignificanceTest::ChiSquare);
+        assert!((comparison.relative_risk.value - 2.0).abs() < 1e-12);
+        assert!((comparison.odds_ratio.value - 30.0 * 85.0 / (70.0 * 15.0)).abs() < 1e-12);
+        let ci = &comparison.relative_risk.ci;
+        assert!(ci.lower > 1.0 && ci.upper > 2.0);
+        assert!(comparison.p_value < 0.05);
+    }
+
+    #[test]
+    fn empty_cells_keep_ratios_finite() {
+        let a = adverse_events(0, 10);
+        let b = adverse_events(4, 6);
+        let comparison = compare(&a.view(), &b.view(), Outcome::AnyEvent);
+
+        assert!(comparison.odds_ratio.value.is_finite());
+        assert!(comparison.relative_risk.ci.upper.is_finite());
+    }
+}
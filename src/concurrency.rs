@@ -0,0 +1,141 @@
+use super::*;
+
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "gen-fake", derive(Dummy))]
+pub struct Concurrency {
+    // Other cases the same anesthesiologist had in progress during this one
+    pub anesthesiologist_cases: usize,
+    // Other cases in progress anywhere in the data set during this one
+    pub facility_cases: usize,
+}
+
+impl AdverseEventRecord {
+    pub fn anesthesia_interval(&self) -> (NaiveDateTime, NaiveDateTime) {
+        let start = self.date.and_time(self.an_start);
+        (start, start + self.anesthesia_duration())
+    }
+}
+
+/// Counts, for each interval, how many of the others overlap it.
+fn overlap_counts(intervals: &[(NaiveDateTime, NaiveDateTime)]) -> Vec<usize> {
+    // Cases without a positive duration can't overlap anything
+    let valid = || intervals.iter().filter(|(start, end)| start < end);
+    let mut starts: Vec<NaiveDateTime> = valid().map(|(start, _)| *start).collect();
+    let mut ends: Vec<NaiveDateTime> = valid().map(|(_, end)| *end).collect();
+    starts.sort();
+    ends.sort();
+
+    intervals
+        .iter()
+        .map(|(start, end)| {
+            if start >= end {
+                return 0;
+            }
+
+            // Every interval ending by our start also started before our
+            // end, so the difference is exactly the overlapping intervals,
+            // ourselves included
+            let started_before_end = starts.partition_point(|s| s < end);
+            let ended_by_start = ends.partition_point(|e| e <= start);
+            started_before_end - ended_by_start - 1
+        })
+        .collect()
+}
+
+pub(crate) fn compute_concurrency(records: &mut [AdverseEventRecord]) {
+    let intervals: Vec<_> = records
+        .iter()
+        .map(|record| record.anesthesia_interval())
+        .collect();
+
+    for (record, count) in records.iter_mut().zip(overlap_counts(&intervals)) {
+        record.concurrency.facility_cases = count;
+    }
+
+    let mut by_anesthesiologist: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, record) in records.iter().enumerate() {
+        by_anesthesiologist
+            .entry(record.anesthesiologist.as_str())
+            .or_default()
+            .push(i);
+    }
+    let by_anesthesiologist: Vec<Vec<usize>> = by_anesthesiologist.into_values().collect();
+
+    for indices in by_anesthesiologist {
+        let group: Vec<_> = indices.iter().map(|i| intervals[*i]).collect();
+        for (i, count) in indices.into_iter().zip(overlap_counts(&group)) {
+            records[i].concurrency.anesthesiologist_cases = count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    fn at(hhmm: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2019, 1, 1)
+            .unwrap()
+            .and_time(NaiveTime::parse_from_str(hhmm, "%H%M").unwrap())
+    }
+
+    #[test]
+    fn overlap_counts_treat_intervals_as_half_open() {
+        let intervals = [
+            (at("0800"), at("1000")),
+            (at("0900"), at("1100")),
+            // Starts as the first ends
+            (at("1000"), at("1200")),
+            (at("1300"), at("1300")),
+            (at("1400"), at("1500")),
+            (at("1400"), at("1500")),
+            (at("2300"), at("2300") + Duration::hours(2)),
+        ];
+
+        assert_eq!(overlap_counts(&intervals), [1, 2, 1, 0, 1, 1, 0]);
+        assert!(overlap_counts(&[]).is_empty());
+    }
+
+    #[test]
+    fn concurrency_counts_facility_and_anesthesiologist_cases() {
+        let adverse_events = test_data::adverse_events(vec![
+            TestRecord {
+                an_start: "0800",
+                an_stop: "1000",
+                ..Default::default()
+            },
+            TestRecord {
+                an_start: "0900",
+                an_stop: "1100",
+                ..Default::default()
+            },
+            TestRecord {
+                anesthesiologist: "Dr B",
+                an_start: "0930",
+                an_stop: "1030",
+                ..Default::default()
+            },
+            // Past midnight, overlapping nothing
+            TestRecord {
+                an_start: "2330",
+                an_stop: "0030",
+                ..Default::default()
+            },
+        ]);
+
+        let concurrency: Vec<(usize, usize)> = adverse_events
+            .records
+            .iter()
+            .map(|record| {
+                (
+                    record.concurrency.facility_cases,
+                    record.concurrency.anesthesiologist_cases,
+                )
+            })
+            .collect();
+        assert_eq!(concurrency, [(2, 1), (2, 1), (2, 0), (0, 0)]);
+    }
+}
@@ -0,0 +1,159 @@
+use super::*;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct CooccurrenceMatrix {
+    pub records: usize,
+    pub events: Vec<String>,
+    // counts[i][j] is the number of records with both events i and j, so the
+    // diagonal holds the number of records with each event
+    pub counts: Vec<Vec<usize>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct EventAssociation {
+    pub antecedent: String,
+    pub consequent: String,
+    pub count: usize,
+    pub support: f64,
+    pub confidence: f64,
+    pub lift: f64,
+}
+
+impl CooccurrenceMatrix {
+    pub fn associations(&self) -> Vec<EventAssociation> {
+        let mut associations = Vec::new();
+
+        for i in 0..self.events.len() {
+            associations.extend(self.associations_for(i));
+        }
+
+        associations
+    }
+
+    pub fn associations_given(&self, event: &str) -> Vec<EventAssociation> {
+        let mut associations = self
+            .events
+            .iter()
+            .position(|e| e == event)
+            .map(|i| self.associations_for(i))
+            .unwrap_or_default();
+
+        associations.sort_by(|a, b| {
+            b.confidence
+                .partial_cmp(&a.confidence)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.consequent.cmp(&b.consequent))
+        });
+
+        associations
+    }
+
+    fn associations_for(&self, antecedent: usize) -> Vec<EventAssociation> {
+        let records = self.records as f64;
+        let antecedent_count = self.counts[antecedent][antecedent] as f64;
+
+        (0..self.events.len())
+            .filter(|&consequent| consequent != antecedent)
+            .filter(|&consequent| self.counts[antecedent][consequent] > 0)
+            .map(|consequent| {
+                let count = self.counts[antecedent][consequent];
+                let consequent_count = self.counts[consequent][consequent] as f64;
+                let confidence = count as f64 / antecedent_count;
+
+                EventAssociation {
+                    antecedent: self.events[antecedent].clone(),
+                    consequent: self.events[consequent].clone(),
+                    count,
+                    support: count as f64 / records,
+                    confidence,
+                    lift: confidence / (consequent_count / records),
+                }
+            })
+            .collect()
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn event_cooccurrence(&self) -> CooccurrenceMatrix {
+        let events: Vec<String> = self
+            .records
+            .iter()
+            .flat_map(|record| record.adverse_events.iter())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .cloned()
+            .collect();
+        let indices: HashMap<&str, usize> = events
+            .iter()
+            .enumerate()
+            .map(|(i, event)| (event.as_str(), i))
+            .collect();
+
+        let mut counts = vec![vec![0; events.len()]; events.len()];
+
+        for record in &self.records {
+            // Events repeated within a record only count once
+            let record_events: BTreeSet<usize> = record
+                .adverse_events
+                .iter()
+                .map(|event| indices[event.as_str()])
+                .collect();
+
+            for &i in &record_events {
+                for &j in &record_events {
+                    counts[i][j] += 1;
+                }
+            }
+        }
+
+        CooccurrenceMatrix {
+            records: self.len(),
+            events,
+            counts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_data::{self, TestRecord};
+
+    #[test]
+    fn cooccurrence_counts_each_record_once() {
+        let events = [
+            "nausea,pain",
+            "nausea,pain",
+            "nausea",
+            "pain,pain",
+            "vomiting",
+            "",
+            "",
+            "",
+        ];
+        let adverse_events =
+            test_data::adverse_events(events.iter().map(|&adverse_events| TestRecord {
+                adverse_events,
+                ..Default::default()
+            }));
+        let matrix = adverse_events.view().event_cooccurrence();
+
+        assert_eq!(matrix.records, 8);
+        assert_eq!(matrix.events, ["nausea", "pain", "vomiting"]);
+        assert_eq!(matrix.counts, [[3, 2, 0], [2, 3, 0], [0, 0, 1]]);
+
+        // Events that never occur together have no rules
+        assert_eq!(matrix.associations().len(), 2);
+        assert!(matrix.associations_given("vomiting").is_empty());
+        assert!(matrix.associations_given("unknown").is_empty());
+
+        let rules = matrix.associations_given("nausea");
+        assert_eq!(rules.len(), 1);
+        let rule = &rules[0];
+        assert_eq!((rule.consequent.as_str(), rule.count), ("pain", 2));
+        assert!((rule.support - 0.25).abs() < 1e-12);
+        assert!((rule.confidence - 2.0 / 3.0).abs() < 1e-12);
+        assert!((rule.lift - 16.0 / 9.0).abs() < 1e-12);
+    }
+}
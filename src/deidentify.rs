@@ -0,0 +1,522 @@
+use super::*;
+
+use hmac::{Hmac, Mac};
+use regex::{Regex, RegexBuilder};
+use sha2::Sha256;
+
+use std::str::FromStr;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdentifierAction {
+    Keep,
+    Drop,
+    // Replaced with a keyed HMAC, so the same value always gets the same
+    // pseudonym under the same key
+    Hash,
+}
+
+impl FromStr for IdentifierAction {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(IdentifierAction::Keep),
+            "drop" => Ok(IdentifierAction::Drop),
+            "hash" => Ok(IdentifierAction::Hash),
+            x => Err(crate::Error::ParseError {
+                type_name: "IdentifierAction",
+                received: x.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FreeTextAction {
+    Keep,
+    Drop,
+    // Removes the patient's name, MRN, dates and long numbers
+    Scrub,
+}
+
+impl FromStr for FreeTextAction {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(FreeTextAction::Keep),
+            "drop" => Ok(FreeTextAction::Drop),
+            "scrub" => Ok(FreeTextAction::Scrub),
+            x => Err(crate::Error::ParseError {
+                type_name: "FreeTextAction",
+                received: x.to_string(),
+            }),
+        }
+    }
+}
+
+/// Shortest key accepted, so pseudonyms can't be brute-forced by guessing
+/// the key.
+pub const MIN_KEY_LEN: usize = 32;
+/// Largest per-patient date shift accepted, about a century.
+pub const MAX_DATE_SHIFT_DAYS: i64 = 36_525;
+
+pub struct DeidentifyConfig {
+    key: Vec<u8>,
+    // Dates and numbers of 5+ digits, removed when scrubbing free text
+    generic_patterns: Vec<Regex>,
+    pub patient_name: IdentifierAction,
+    pub mrn: IdentifierAction,
+    pub episode_id: IdentifierAction,
+    // Dates move by a per-patient offset of up to this many days either way
+    pub max_date_shift_days: i64,
+    // Ages above this are recorded as one more than it
+    pub max_age: u8,
+    // Applies to diagnosis, procedure and kept text attributes
+    pub free_text: FreeTextAction,
+    // Attributes to keep, with text handled like free text and dates
+    // shifted; every other attribute is removed
+    pub kept_attributes: BTreeSet<String>,
+}
+
+impl DeidentifyConfig {
+    pub fn new(key: Vec<u8>) -> Result<Self, Error> {
+        if key.len() < MIN_KEY_LEN {
+            return Err(Error::InvalidConfig(
+                "the de-identification key must be at least 32 bytes",
+            ));
+        }
+
+        Ok(DeidentifyConfig {
+            key,
+            generic_patterns: [r"\b\d{1,4}[/-]\d{1,2}[/-]\d{1,4}\b", r"\b\d{5,}\b"]
+                .iter()
+                .map(|pattern| Regex::new(pattern).expect("generic patterns are valid"))
+                .collect(),
+            patient_name: IdentifierAction::Drop,
+            mrn: IdentifierAction::Hash,
+            episode_id: IdentifierAction::Hash,
+            max_date_shift_days: 180,
+            max_age: 89,
+            free_text: FreeTextAction::Scrub,
+            kept_attributes: BTreeSet::new(),
+        })
+    }
+
+    fn mac(&self, domain: &str, value: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(domain.as_bytes());
+        mac.update(&[0]);
+        mac.update(value.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    pub fn pseudonym(&self, domain: &str, value: &str) -> String {
+        self.mac(domain, value)
+            .iter()
+            .take(16)
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn date_shift(&self, patient: &str) -> Result<Duration, Error> {
+        if self.max_date_shift_days <= 0 {
+            return Ok(Duration::days(0));
+        }
+
+        let span = Some(self.max_date_shift_days)
+            .filter(|days| *days <= MAX_DATE_SHIFT_DAYS)
+            .and_then(|days| days.checked_mul(2))
+            .and_then(|days| days.checked_add(1))
+            .ok_or(Error::InvalidConfig(
+                "the maximum date shift can be at most 36525 days",
+            ))?;
+
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.mac("date-shift", patient)[..8]);
+        let offset = (u64::from_le_bytes(bytes) % span as u64) as i64;
+        Ok(Duration::days(offset - self.max_date_shift_days))
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct AppliedTransformation {
+    pub field: String,
+    pub transformation: String,
+    pub records_changed: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct DeidentificationReport {
+    pub records: usize,
+    pub patients: usize,
+    pub transformations: Vec<AppliedTransformation>,
+}
+
+fn apply_identifier(
+    config: &DeidentifyConfig,
+    action: IdentifierAction,
+    domain: &str,
+    value: &mut String,
+) -> bool {
+    match action {
+        IdentifierAction::Keep => false,
+        IdentifierAction::Drop => {
+            let changed = !value.is_empty();
+            value.clear();
+            changed
+        }
+        IdentifierAction::Hash => {
+            if value.trim().is_empty() {
+                false
+            } else {
+                *value = config.pseudonym(domain, value.trim());
+                true
+            }
+        }
+    }
+}
+
+fn scrub(text: &mut String, patterns: &[Regex]) -> bool {
+    let mut scrubbed = text.clone();
+    for pattern in patterns {
+        scrubbed = pattern.replace_all(&scrubbed, "[removed]").into_owned();
+    }
+
+    let changed = scrubbed != *text;
+    *text = scrubbed;
+    changed
+}
+
+fn identifier_description(action: IdentifierAction) -> Option<&'static str> {
+    match action {
+        IdentifierAction::Keep => None,
+        IdentifierAction::Drop => Some("removed"),
+        IdentifierAction::Hash => Some("replaced with keyed HMAC-SHA256 pseudonym"),
+    }
+}
+
+impl AdverseEvents {
+    /// De-identifies every record in place, returning what was done.
+    ///
+    /// Date shifts are keyed on the original MRN, so every episode for a
+    /// patient moves together and intervals between them are preserved.
+    pub fn deidentify(
+        &mut self,
+        config: &DeidentifyConfig,
+    ) -> Result<DeidentificationReport, Error> {
+        // Checked up front so a bad config doesn't leave records half done
+        config.date_shift("")?;
+
+        let mut patients: Vec<String> = Vec::new();
+        let mut counts: HashMap<&'static str, usize> = HashMap::new();
+        let mut count = |field: &'static str, changed: bool| {
+            if changed {
+                *counts.entry(field).or_default() += 1;
+            }
+        };
+
+        for record in &mut self.records {
+            let patient = if record.mrn.trim().is_empty() {
+                record.episode_id.trim().to_string()
+            } else {
+                record.mrn.trim().to_string()
+            };
+            patients.push(patient.clone());
+
+            let attributes = record.attributes.len();
+            record
+                .attributes
+                .retain(|name, _| config.kept_attributes.contains(name));
+            let mut attributes_changed = record.attributes.len() != attributes;
+
+            if config.free_text != FreeTextAction::Keep {
+                let mut patterns = Vec::new();
+                if config.free_text == FreeTextAction::Scrub {
+                    patterns.extend(config.generic_patterns.iter().cloned());
+                    for identifier in record
+                        .patient_name
+                        .split(|c: char| !c.is_alphanumeric())
+                        .chain(std::iter::once(record.mrn.trim()))
+                        .filter(|part| part.len() > 1)
+                    {
+                        patterns.push(
+                            RegexBuilder::new(&format!(r"\b{}\b", regex::escape(identifier)))
+                                .case_insensitive(true)
+                                .build()
+                                .unwrap(),
+                        );
+                    }
+                }
+
+                let free_text = |text: &mut String| match config.free_text {
+                    FreeTextAction::Keep => false,
+                    FreeTextAction::Drop => {
+                        let changed = !text.is_empty();
+                        text.clear();
+                        changed
+                    }
+                    FreeTextAction::Scrub => scrub(text, &patterns),
+                };
+
+                count("diagnosis", free_text(&mut record.diagnosis));
+                count("procedure", free_text(&mut record.procedure));
+                for value in record.attributes.values_mut() {
+                    if let AttributeValue::Text(text) = value {
+                        attributes_changed |= free_text(text);
+                    }
+                }
+            }
+            count("attributes", attributes_changed);
+
+            count(
+                "patientName",
+                apply_identifier(
+                    config,
+                    config.patient_name,
+                    "patient-name",
+                    &mut record.patient_name,
+                ),
+            );
+            count(
+                "mrn",
+                apply_identifier(config, config.mrn, "mrn", &mut record.mrn),
+            );
+            count(
+                "episodeId",
+                apply_identifier(
+                    config,
+                    config.episode_id,
+                    "episode-id",
+                    &mut record.episode_id,
+                ),
+            );
+
+            let shift = config.date_shift(&patient)?;
+            if shift.num_days() != 0 {
+                record.date += shift;
+                for value in record.attributes.values_mut() {
+                    if let AttributeValue::Date(date) = value {
+                        *date += shift;
+                    }
+                }
+                count("date", true);
+            }
+
+            if record.age > config.max_age {
+                record.age = config.max_age.saturating_add(1);
+                count("age", true);
+            }
+        }
+
+        patients.sort_unstable();
+        patients.dedup();
+
+        let free_text_description = match config.free_text {
+            FreeTextAction::Keep => None,
+            FreeTextAction::Drop => Some("removed".to_string()),
+            FreeTextAction::Scrub => Some(
+                "patient name, MRN, dates and numbers of 5+ digits replaced with [removed]"
+                    .to_string(),
+            ),
+        };
+
+        let transformations = vec![
+            (
+                "patientName",
+                identifier_description(config.patient_name).map(str::to_string),
+            ),
+            (
+                "mrn",
+                identifier_description(config.mrn).map(str::to_string),
+            ),
+            (
+                "episodeId",
+                identifier_description(config.episode_id).map(str::to_string),
+            ),
+            (
+                "date",
+                Some(config.max_date_shift_days)
+                    .filter(|days| *days > 0)
+                    .map(|days| format!("shifted by a per-patient offset of up to ±{} days", days)),
+            ),
+            (
+                "age",
+                Some(format!(
+                    "ages above {} recorded as {}",
+                    config.max_age,
+                    config.max_age.saturating_add(1)
+                )),
+            ),
+            ("diagnosis", free_text_description.clone()),
+            ("procedure", free_text_description.clone()),
+            (
+                "attributes",
+                Some(if config.kept_attributes.is_empty() {
+                    "removed".to_string()
+                } else {
+                    format!(
+                        "removed except {}{}",
+                        config
+                            .kept_attributes
+                            .iter()
+                            .map(String::as_str)
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        free_text_description
+                            .map(|description| format!("; text values: {}", description))
+                            .unwrap_or_default()
+                    )
+                }),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(field, transformation)| {
+            transformation.map(|transformation| AppliedTransformation {
+                field: field.to_string(),
+                transformation,
+                records_changed: counts.get(field).copied().unwrap_or_default(),
+            })
+        })
+        .collect();
+
+        Ok(DeidentificationReport {
+            records: self.records.len(),
+            patients: patients.len(),
+            transformations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    fn adverse_events() -> AdverseEvents {
+        let jane = TestRecord {
+            mrn: Some("M12345".to_string()),
+            patient_name: "Jane Doe",
+            age: 95,
+            ..Default::default()
+        };
+        let mut adverse_events = test_data::adverse_events(vec![
+            TestRecord {
+                date: "03/10/19",
+                diagnosis: "Doe fell on 03/01/19",
+                ..jane.clone()
+            },
+            TestRecord {
+                date: "03/20/19",
+                ..jane
+            },
+            TestRecord {
+                date: "03/20/19",
+                mrn: Some("M67890".to_string()),
+                patient_name: "John Roe",
+                age: 40,
+                ..Default::default()
+            },
+        ]);
+        for record in &mut adverse_events.records {
+            record.attributes.insert(
+                "Notes".to_string(),
+                AttributeValue::Text(format!("seen by {}", record.patient_name)),
+            );
+            record
+                .attributes
+                .insert("Flag".to_string(), AttributeValue::Bool(true));
+        }
+
+        adverse_events
+    }
+
+    #[test]
+    fn new_rejects_short_keys() {
+        assert!(matches!(
+            DeidentifyConfig::new(vec![0; MIN_KEY_LEN - 1]),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(DeidentifyConfig::new(vec![0; MIN_KEY_LEN]).is_ok());
+    }
+
+    #[test]
+    fn pseudonyms_depend_on_key_and_domain() {
+        let config = DeidentifyConfig::new(vec![1; 32]).unwrap();
+        let other = DeidentifyConfig::new(vec![2; 32]).unwrap();
+
+        assert_eq!(config.pseudonym("mrn", "M1"), config.pseudonym("mrn", "M1"));
+        assert_eq!(config.pseudonym("mrn", "M1").len(), 32);
+        assert_ne!(config.pseudonym("mrn", "M1"), config.pseudonym("mrn", "M2"));
+        assert_ne!(
+            config.pseudonym("mrn", "M1"),
+            config.pseudonym("episode-id", "M1")
+        );
+        assert_ne!(config.pseudonym("mrn", "M1"), other.pseudonym("mrn", "M1"));
+    }
+
+    #[test]
+    fn date_shift_stays_within_bounds() {
+        let mut config = DeidentifyConfig::new(vec![1; 32]).unwrap();
+        config.max_date_shift_days = 3;
+        for patient in 0..100 {
+            let days = config.date_shift(&patient.to_string()).unwrap().num_days();
+            assert!((-3..=3).contains(&days));
+        }
+
+        config.max_date_shift_days = 0;
+        assert_eq!(config.date_shift("M1").unwrap().num_days(), 0);
+
+        config.max_date_shift_days = MAX_DATE_SHIFT_DAYS + 1;
+        assert!(matches!(
+            config.date_shift("M1"),
+            Err(Error::InvalidConfig(_))
+        ));
+        let mut adverse_events = adverse_events();
+        assert!(adverse_events.deidentify(&config).is_err());
+        assert_eq!(adverse_events.records[0].patient_name, "Jane Doe");
+    }
+
+    #[test]
+    fn deidentify_keeps_intervals_and_links() {
+        let mut config = DeidentifyConfig::new(vec![1; 32]).unwrap();
+        config.kept_attributes.insert("Notes".to_string());
+        let mut adverse_events = adverse_events();
+
+        let report = adverse_events.deidentify(&config).unwrap();
+        assert_eq!((report.records, report.patients), (3, 2));
+
+        let records = &adverse_events.records;
+        assert_eq!(records[1].date - records[0].date, Duration::days(10));
+        assert_eq!(records[0].mrn, records[1].mrn);
+        assert_ne!(records[0].mrn, "M12345");
+        assert_ne!(records[0].episode_id, records[1].episode_id);
+        assert!(records[0].patient_name.is_empty());
+        assert_eq!(records[0].diagnosis, "[removed] fell on [removed]");
+        assert_eq!((records[0].age, records[2].age), (90, 40));
+
+        // Attributes are dropped unless kept, and kept text is scrubbed
+        assert!(records
+            .iter()
+            .all(|record| !record.attributes.contains_key("Flag")));
+        assert_eq!(
+            records[0].attributes.get("Notes"),
+            Some(&AttributeValue::Text(
+                "seen by [removed] [removed]".to_string()
+            ))
+        );
+        let attributes = report
+            .transformations
+            .iter()
+            .find(|transformation| transformation.field == "attributes")
+            .unwrap();
+        assert_eq!(attributes.records_changed, 3);
+        assert!(attributes
+            .transformation
+            .starts_with("removed except Notes"));
+    }
+}
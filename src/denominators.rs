@@ -0,0 +1,621 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum DenominatorSource {
+    // Cases in the loaded data set itself
+    Records,
+    // A separate list with one row per case
+    CaseList,
+    // Case counts per date, location and/or provider
+    AggregateCounts,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DenominatorRow {
+    pub date: NaiveDate,
+    pub location: Option<String>,
+    pub anesthesiologist: Option<String>,
+    pub cases: usize,
+}
+
+/// True case volumes for extracts that only contain cases with events.
+///
+/// Read from a CSV with a `Date` column and optional `Location` and
+/// `Anesthesiologist` columns. If it has a `Cases` column each row is an
+/// aggregate count, otherwise each row is a single case.
+#[derive(Debug, Clone)]
+pub struct Denominators {
+    pub source: DenominatorSource,
+    rows: Vec<DenominatorRow>,
+}
+
+impl Denominators {
+    pub fn from_csv_reader<R>(reader: R) -> Result<Self, Error>
+    where
+        R: Read,
+    {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+
+        let headers = reader.headers()?.clone();
+        let position = |column: &str| headers.iter().position(|header| header.trim() == column);
+
+        let date_position =
+            position("Date").ok_or(Error::InsufficientData("denominators have no Date column"))?;
+        let location_position = position("Location");
+        let anesthesiologist_position = position("Anesthesiologist");
+        let cases_position = position("Cases");
+
+        let mut rows = Vec::new();
+        for row in reader.records() {
+            let row = row?;
+            let text = |i: Option<usize>| {
+                i.and_then(|i| row.get(i))
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_string)
+            };
+
+            let date = match AttributeValue::parse(
+                AttributeType::Date,
+                row.get(date_position).unwrap_or(""),
+            )? {
+                Some(AttributeValue::Date(date)) => date,
+                _ => continue,
+            };
+
+            let cases = match cases_position {
+                Some(i) => {
+                    let cases = row.get(i).unwrap_or("").trim();
+                    cases.parse().map_err(|_| Error::ParseError {
+                        type_name: "DenominatorCases",
+                        received: cases.to_string(),
+                    })?
+                }
+                None => 1,
+            };
+
+            rows.push(DenominatorRow {
+                date,
+                location: text(location_position),
+                anesthesiologist: text(anesthesiologist_position),
+                cases,
+            });
+        }
+
+        Ok(Denominators {
+            source: if cases_position.is_some() {
+                DenominatorSource::AggregateCounts
+            } else {
+                DenominatorSource::CaseList
+            },
+            rows,
+        })
+    }
+
+    pub fn rows(&self) -> &[DenominatorRow] {
+        &self.rows
+    }
+
+    pub fn total(&self) -> usize {
+        self.cases(|_| true)
+    }
+
+    pub fn cases<F>(&self, mut filter: F) -> usize
+    where
+        F: FnMut(&DenominatorRow) -> bool,
+    {
+        self.rows
+            .iter()
+            .filter(|row| filter(row))
+            .map(|row| row.cases)
+            .sum()
+    }
+
+    pub fn with_filter<F>(&self, mut filter: F) -> Self
+    where
+        F: FnMut(&DenominatorRow) -> bool,
+    {
+        Denominators {
+            source: self.source,
+            rows: self
+                .rows
+                .iter()
+                .filter(|row| filter(row))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn between(&self, start: NaiveDate, end: NaiveDate) -> Self {
+        self.with_filter(|row| start <= row.date && row.date <= end)
+    }
+
+    pub fn at_location(&self, location: &str) -> Self {
+        self.with_filter(|row| row.location.as_deref() == Some(location))
+    }
+
+    pub fn by_anesthesiologist(&self, anesthesiologist: &str) -> Self {
+        self.with_filter(|row| row.anesthesiologist.as_deref() == Some(anesthesiologist))
+    }
+
+    /// First and last dates with cases.
+    pub fn date_range(&self) -> Option<(NaiveDate, NaiveDate)> {
+        let min = self.rows.iter().map(|row| row.date).min()?;
+        let max = self.rows.iter().map(|row| row.date).max()?;
+        Some((min, max))
+    }
+
+    /// Cases falling in the same group a record labeled `label` would be
+    /// in, for the dimensions denominators can be split by.
+    fn group_cases(&self, dimension: &Dimension, label: &str) -> Result<usize, Error> {
+        match dimension {
+            Dimension::Location => Ok(self.cases(|row| row.location.as_deref() == Some(label))),
+            Dimension::Anesthesiologist => {
+                Ok(self.cases(|row| row.anesthesiologist.as_deref() == Some(label)))
+            }
+            _ => Err(Error::InsufficientData(
+                "denominators can only be grouped by location, anesthesiologist or period",
+            )),
+        }
+    }
+}
+
+/// The filters denominators can follow, since the denominator file has the
+/// same columns: a date window, a location and an anesthesiologist.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DenominatorFilter {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub location: Option<String>,
+    pub anesthesiologist: Option<String>,
+}
+
+impl DenominatorFilter {
+    fn matches(
+        &self,
+        date: NaiveDate,
+        location: Option<&str>,
+        anesthesiologist: Option<&str>,
+    ) -> bool {
+        self.from.iter().all(|from| *from <= date)
+            && self.to.iter().all(|to| date <= *to)
+            && self.location.iter().all(|l| location == Some(l.as_str()))
+            && self
+                .anesthesiologist
+                .iter()
+                .all(|a| anesthesiologist == Some(a.as_str()))
+    }
+
+    pub fn matches_record(&self, record: &AdverseEventRecord) -> bool {
+        self.matches(
+            record.date,
+            Some(&record.location),
+            Some(&record.anesthesiologist),
+        )
+    }
+
+    pub fn matches_row(&self, row: &DenominatorRow) -> bool {
+        self.matches(
+            row.date,
+            row.location.as_deref(),
+            row.anesthesiologist.as_deref(),
+        )
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct Denominated<T: Serialize> {
+    pub denominator: DenominatorSource,
+    pub value: T,
+}
+
+impl AdverseEvents {
+    pub fn set_denominators(&mut self, denominators: Denominators) {
+        self.denominators = Some(denominators);
+    }
+
+    /// The loaded denominators narrowed by the same filter as `view`. With
+    /// no date filter they keep their full range, since an extract of
+    /// events alone doesn't show when cases started or stopped.
+    ///
+    /// A view narrowed by anything the filter doesn't cover is refused
+    /// rather than divided by cases it doesn't represent.
+    pub fn view_denominators(
+        &self,
+        view: &AdverseEventsView,
+        filter: &DenominatorFilter,
+    ) -> Result<Option<Denominators>, Error> {
+        let denominators = match &self.denominators {
+            Some(denominators) => denominators,
+            None => return Ok(None),
+        };
+
+        let matching = self
+            .records
+            .iter()
+            .filter(|record| filter.matches_record(record))
+            .count();
+        if view.len() != matching || !view.records.iter().all(|r| filter.matches_record(r)) {
+            return Err(Error::InsufficientData(
+                "denominators only follow date, location and anesthesiologist filters",
+            ));
+        }
+        if filter.location.is_some() && denominators.rows.iter().all(|r| r.location.is_none()) {
+            return Err(Error::InsufficientData(
+                "denominators have no Location column",
+            ));
+        }
+        if filter.anesthesiologist.is_some()
+            && denominators
+                .rows
+                .iter()
+                .all(|row| row.anesthesiologist.is_none())
+        {
+            return Err(Error::InsufficientData(
+                "denominators have no Anesthesiologist column",
+            ));
+        }
+
+        Ok(Some(
+            denominators.with_filter(|row| filter.matches_row(row)),
+        ))
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    /// From the first to the last date with either a record or a
+    /// denominator case, so periods with cases but no events still count.
+    fn denominated_range(&self, denominators: &Denominators) -> Option<(NaiveDate, NaiveDate)> {
+        match (self.date_range(), denominators.date_range()) {
+            (Some((min, max)), Some((first, last))) => Some((min.min(first), max.max(last))),
+            (range, None) | (None, range) => range,
+        }
+    }
+
+    /// Like `period_percentages`, but divided by the denominator's cases in
+    /// each period when one is given.
+    pub fn denominated_percentages<F>(
+        &self,
+        period: Period,
+        filter: F,
+        denominators: Option<&Denominators>,
+    ) -> Denominated<Vec<DatePeriodPercentage>>
+    where
+        F: FnMut(&&&AdverseEventRecord) -> bool,
+    {
+        let denominators = match denominators {
+            Some(denominators) => denominators,
+            None => {
+                return Denominated {
+                    denominator: DenominatorSource::Records,
+                    value: self.period_percentages(period, filter),
+                }
+            }
+        };
+
+        let mut matching: HashMap<NaiveDate, usize> = self
+            .by_period(period, filter)
+            .into_iter()
+            .map(|dpv| (dpv.start, dpv.value.len()))
+            .collect();
+
+        // Every period with records or cases, so periods without any
+        // matching records still report a zero rate
+        let value = match self.denominated_range(denominators) {
+            Some((min, max)) => period_bounds(period, min, max)
+                .into_iter()
+                .map(|(start, end)| {
+                    let matching_count = matching.remove(&start).unwrap_or_default();
+                    let total_count =
+                        denominators.cases(|row| start <= row.date && row.date <= end);
+
+                    DatePeriodPercentage {
+                        period,
+                        start,
+                        end,
+                        value: if total_count == 0 {
+                            0.0
+                        } else {
+                            matching_count as f64 / total_count as f64 * 100.0
+                        },
+                    }
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Denominated {
+            denominator: denominators.source,
+            value,
+        }
+    }
+
+    pub fn denominated_timeseries(
+        &self,
+        timeseries_type: TimeseriesType,
+        period: Period,
+        denominators: Option<&Denominators>,
+    ) -> Denominated<Timeseries> {
+        let percentages = |filter: fn(&&&AdverseEventRecord) -> bool| {
+            let denominated = self.denominated_percentages(period, filter, denominators);
+            Denominated {
+                denominator: denominated.denominator,
+                value: Timeseries::Percentages(denominated.value),
+            }
+        };
+
+        match timeseries_type {
+            TimeseriesType::EventPercentage => {
+                percentages(|record| !record.adverse_events.is_empty())
+            }
+            TimeseriesType::ComplicationSpecifiedPercentage => {
+                percentages(|record| record.complications.is_some())
+            }
+            TimeseriesType::ComplicationOccurredPercentage => {
+                percentages(|record| record.complications == Some(true))
+            }
+            timeseries_type => Denominated {
+                denominator: DenominatorSource::Records,
+                value: self.timeseries(timeseries_type, period),
+            },
+        }
+    }
+
+    /// Breakdown rates, which external denominators can't be split into
+    /// the categories of.
+    pub fn denominated_breakdown_rates(
+        &self,
+        breakdown_type: BreakdownType,
+        outcome: &Outcome,
+        denominators: Option<&Denominators>,
+    ) -> Result<Denominated<Vec<LabeledRate>>, Error> {
+        match denominators {
+            Some(_) => Err(Error::InsufficientData(
+                "denominators can't be broken down by patient or case characteristics",
+            )),
+            None => Ok(Denominated {
+                denominator: DenominatorSource::Records,
+                value: self.get_breakdown_rates(breakdown_type, outcome),
+            }),
+        }
+    }
+
+    pub fn denominated_rates(
+        &self,
+        dimension: &Dimension,
+        outcome: &Outcome,
+        denominators: Option<&Denominators>,
+    ) -> Result<Denominated<Vec<LabeledRate>>, Error> {
+        let denominators = match denominators {
+            Some(denominators) => denominators,
+            None => {
+                return Ok(Denominated {
+                    denominator: DenominatorSource::Records,
+                    value: self
+                        .group_by_dimension(dimension)
+                        .into_iter()
+                        .map(|group| LabeledRate::new(group.label, &group.value, outcome))
+                        .collect(),
+                })
+            }
+        };
+
+        let value = match dimension {
+            Dimension::Period(period) => match self.denominated_range(denominators) {
+                Some((min, max)) => period_bounds(*period, min, max)
+                    .into_iter()
+                    .map(|(start, end)| {
+                        LabeledRate::from_counts(
+                            start.to_string(),
+                            denominators.cases(|row| start <= row.date && row.date <= end),
+                            self.between(start, end).count_outcome(outcome),
+                        )
+                    })
+                    .collect(),
+                None => Vec::new(),
+            },
+            dimension => self
+                .group_by_dimension(dimension)
+                .into_iter()
+                .map(|group| {
+                    Ok(LabeledRate::from_counts(
+                        group.label.clone(),
+                        denominators.group_cases(dimension, &group.label)?,
+                        group.value.count_outcome(outcome),
+                    ))
+                })
+                .collect::<Result<_, Error>>()?,
+        };
+
+        Ok(Denominated {
+            denominator: denominators.source,
+            value,
+        })
+    }
+}
+
+/// Start and end of every period from the one containing `min` through the
+/// one containing `max`, matching the bounds `by_period` uses.
+fn period_bounds(period: Period, min: NaiveDate, max: NaiveDate) -> Vec<(NaiveDate, NaiveDate)> {
+    let start_of = |date: NaiveDate| match period {
+        Period::Day => date,
+        Period::Week => {
+            NaiveDate::from_isoywd(date.iso_week().year(), date.iso_week().week(), Weekday::Mon)
+        }
+        Period::Month => NaiveDate::from_ymd(date.year(), date.month(), 1),
+        Period::Year => NaiveDate::from_ymd(date.year(), 1, 1),
+    };
+    let next = |date: NaiveDate| match period {
+        Period::Day => date + Duration::days(1),
+        Period::Week => date + Duration::weeks(1),
+        Period::Month if date.month() == 12 => NaiveDate::from_ymd(date.year() + 1, 1, 1),
+        Period::Month => NaiveDate::from_ymd(date.year(), date.month() + 1, 1),
+        Period::Year => NaiveDate::from_ymd(date.year() + 1, 1, 1),
+    };
+
+    let mut bounds = Vec::new();
+    let mut start = start_of(min);
+    while start <= max {
+        let following = next(start);
+        bounds.push((start, following - Duration::days(1)));
+        start = following;
+    }
+
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    const DENOMINATORS: &str = "\
+Date,Location,Anesthesiologist,Cases
+01/01/19,OR,Dr A,10
+01/15/19,OR,Dr B,5
+02/01/19,PACU,Dr A,4
+03/01/19,OR,Dr A,1
+";
+
+    fn adverse_events() -> AdverseEvents {
+        let record = |date, location, anesthesiologist, event| TestRecord {
+            date,
+            location,
+            anesthesiologist,
+            ..TestRecord::with_event(event)
+        };
+
+        let mut adverse_events = test_data::adverse_events(vec![
+            record("01/01/19", "OR", "Dr A", true),
+            record("01/15/19", "OR", "Dr B", false),
+            record("02/01/19", "PACU", "Dr A", true),
+        ]);
+        adverse_events
+            .set_denominators(Denominators::from_csv_reader(DENOMINATORS.as_bytes()).unwrap());
+        adverse_events
+    }
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2019, month, day)
+    }
+
+    #[test]
+    fn from_csv_reader_reads_counts_and_case_lists() {
+        let counts = Denominators::from_csv_reader(DENOMINATORS.as_bytes()).unwrap();
+        assert_eq!(counts.source, DenominatorSource::AggregateCounts);
+        assert_eq!(counts.total(), 20);
+        assert_eq!(counts.date_range(), Some((date(1, 1), date(3, 1))));
+        assert_eq!(counts.at_location("OR").total(), 16);
+        assert_eq!(counts.by_anesthesiologist("Dr A").total(), 15);
+        assert_eq!(counts.between(date(1, 2), date(2, 1)).total(), 9);
+
+        let cases =
+            Denominators::from_csv_reader("Date\n01/01/19\n\n01/02/19\n".as_bytes()).unwrap();
+        assert_eq!(cases.source, DenominatorSource::CaseList);
+        assert_eq!(cases.total(), 2);
+        assert_eq!(cases.rows()[0].location, None);
+
+        assert!(Denominators::from_csv_reader("Location\nOR\n".as_bytes()).is_err());
+        assert!(Denominators::from_csv_reader("Date,Cases\n01/01/19,x\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn view_denominators_follow_supported_filters_only() {
+        let adverse_events = adverse_events();
+        let filter = DenominatorFilter {
+            from: Some(date(1, 1)),
+            to: Some(date(1, 31)),
+            location: Some("OR".to_string()),
+            ..Default::default()
+        };
+        let view = adverse_events
+            .view()
+            .with_filter(|record| filter.matches_record(record));
+
+        let denominators = adverse_events.view_denominators(&view, &filter).unwrap();
+        assert_eq!(denominators.map(|d| d.total()), Some(15));
+
+        let with_event = adverse_events.view().with_any_event();
+        assert!(adverse_events
+            .view_denominators(&with_event, &DenominatorFilter::default())
+            .is_err());
+
+        let without_denominators = test_data::adverse_events(vec![TestRecord::default()]);
+        assert!(without_denominators
+            .view_denominators(&without_denominators.view(), &DenominatorFilter::default())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn denominated_percentages_cover_periods_without_events() {
+        let adverse_events = adverse_events();
+        let view = adverse_events.view();
+        let denominators = adverse_events
+            .view_denominators(&view, &DenominatorFilter::default())
+            .unwrap();
+
+        let denominated = view.denominated_percentages(
+            Period::Month,
+            |record| !record.adverse_events.is_empty(),
+            denominators.as_ref(),
+        );
+        assert_eq!(denominated.denominator, DenominatorSource::AggregateCounts);
+        let percentages: Vec<(NaiveDate, f64)> = denominated
+            .value
+            .iter()
+            .map(|percentage| (percentage.start, percentage.value))
+            .collect();
+        assert_eq!(
+            percentages,
+            [
+                (date(1, 1), 100.0 / 15.0),
+                (date(2, 1), 25.0),
+                (date(3, 1), 0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn denominated_rates_by_group() {
+        let adverse_events = adverse_events();
+        let view = adverse_events.view();
+        let denominators = adverse_events
+            .view_denominators(&view, &DenominatorFilter::default())
+            .unwrap();
+        let denominators = denominators.as_ref();
+
+        let rates = view
+            .denominated_rates(&Dimension::Location, &Outcome::AnyEvent, denominators)
+            .unwrap();
+        let counts: Vec<(&str, usize, usize)> = rates
+            .value
+            .iter()
+            .map(|rate| (rate.label.as_str(), rate.total, rate.with_outcome))
+            .collect();
+        assert_eq!(counts, [("OR", 16, 1), ("PACU", 4, 1)]);
+
+        let by_month = view
+            .denominated_rates(
+                &Dimension::Period(Period::Month),
+                &Outcome::AnyEvent,
+                denominators,
+            )
+            .unwrap();
+        assert_eq!(by_month.value.len(), 3);
+        assert_eq!(by_month.value[2].total, 1);
+
+        assert!(view
+            .denominated_rates(&Dimension::Asa, &Outcome::AnyEvent, denominators)
+            .is_err());
+        assert!(view
+            .denominated_breakdown_rates(
+                BreakdownType::PatientSmoker,
+                &Outcome::AnyEvent,
+                denominators
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use super::*;
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dimension {
+    Anesthesiologist,
+    Location,
+    Procedure,
+    Diagnosis,
+    Asa,
+    Smoker,
+    StaffCount,
+    ConcurrentCases,
+    FacilityConcurrentCases,
+    PriorEvent,
+    Attribute(String),
+    Period(Period),
+    Binned(NumericField, Binning),
+}
+
+impl FromStr for Dimension {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "anesthesiologist" => Ok(Dimension::Anesthesiologist),
+            "location" => Ok(Dimension::Location),
+            "procedure" => Ok(Dimension::Procedure),
+            "diagnosis" => Ok(Dimension::Diagnosis),
+            "asa" => Ok(Dimension::Asa),
+            "smoker" => Ok(Dimension::Smoker),
+            "staffCount" => Ok(Dimension::StaffCount),
+            "concurrentCases" => Ok(Dimension::ConcurrentCases),
+            "facilityConcurrentCases" => Ok(Dimension::FacilityConcurrentCases),
+            "priorEvent" => Ok(Dimension::PriorEvent),
+            x => {
+                let err = || crate::Error::ParseError {
+                    type_name: "Dimension",
+                    received: x.to_string(),
+                };
+
+                // Attributes are grouped by value, or binned when given as
+                // `attribute:<name>:<binning>`
+                if let Some(attribute) = x.strip_prefix("attribute:") {
+                    return match attribute.find(':') {
+                        Some(i) => Ok(Dimension::Binned(
+                            NumericField::Attribute(attribute[..i].to_string()),
+                            Binning::from_str(&attribute[i + 1..]).map_err(|_| err())?,
+                        )),
+                        None => Ok(Dimension::Attribute(attribute.to_string())),
+                    };
+                }
+
+                // Binned numeric fields are given as `<field>:<binning>`,
+                // e.g. `age:width:10` or `bmi:bmiCategories`
+                match x.find(':') {
+                    Some(i) => Ok(Dimension::Binned(
+                        NumericField::from_str(&x[..i]).map_err(|_| err())?,
+                        Binning::from_str(&x[i + 1..]).map_err(|_| err())?,
+                    )),
+                    None => Period::from_str(x)
+                        .map(Dimension::Period)
+                        .map_err(|_| err()),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DimensionGroup<'a> {
+    pub label: String,
+    pub value: AdverseEventsView<'a>,
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn group_by_dimension(&self, dimension: &Dimension) -> Vec<DimensionGroup<'a>> {
+        match dimension {
+            Dimension::Anesthesiologist => {
+                self.group_by_label(|record| record.anesthesiologist.clone())
+            }
+            Dimension::Location => self.group_by_label(|record| record.location.clone()),
+            Dimension::Procedure => self.group_by_label(|record| record.procedure.clone()),
+            Dimension::Diagnosis => self.group_by_label(|record| record.diagnosis.clone()),
+            Dimension::Asa => sort_map(group_by_owned(self.records.iter().copied(), |record| {
+                record.asa
+            }))
+            .into_iter()
+            .map(|(asa, records)| DimensionGroup {
+                label: format!("ASA {}", asa),
+                value: AdverseEventsView { records },
+            })
+            .collect(),
+            Dimension::Smoker => {
+                vec![
+                    DimensionGroup {
+                        label: "Smoker".to_string(),
+                        value: self.with_filter(|record| record.smoker),
+                    },
+                    DimensionGroup {
+                        label: "Non-smoker".to_string(),
+                        value: self.with_filter(|record| !record.smoker),
+                    },
+                ]
+            }
+            Dimension::StaffCount => {
+                sort_map(group_by_owned(self.records.iter().copied(), |record| {
+                    record.staff().len()
+                }))
+                .into_iter()
+                .map(|(count, records)| DimensionGroup {
+                    label: match count {
+                        1 => "1 staff member".to_string(),
+                        n => format!("{} staff members", n),
+                    },
+                    value: AdverseEventsView { records },
+                })
+                .collect()
+            }
+            Dimension::ConcurrentCases => {
+                sort_map(group_by_owned(self.records.iter().copied(), |record| {
+                    record.concurrency.anesthesiologist_cases
+                }))
+                .into_iter()
+                .map(|(count, records)| DimensionGroup {
+                    label: format!("{} concurrent", count),
+                    value: AdverseEventsView { records },
+                })
+                .collect()
+            }
+            Dimension::FacilityConcurrentCases => {
+                sort_map(group_by_owned(self.records.iter().copied(), |record| {
+                    record.concurrency.facility_cases
+                }))
+                .into_iter()
+                .map(|(count, records)| DimensionGroup {
+                    label: format!("{} concurrent", count),
+                    value: AdverseEventsView { records },
+                })
+                .collect()
+            }
+            Dimension::PriorEvent => {
+                vec![
+                    DimensionGroup {
+                        label: "Prior event".to_string(),
+                        value: self.with_prior_event(None),
+                    },
+                    DimensionGroup {
+                        label: "No prior event".to_string(),
+                        value: self.with_filter(|record| record.days_since_prior_event.is_none()),
+                    },
+                ]
+            }
+            Dimension::Attribute(name) => self.group_by_label(|record| {
+                record
+                    .attributes
+                    .get(name)
+                    .map(|value| value.to_string())
+                    .unwrap_or_else(|| "Unspecified".to_string())
+            }),
+            Dimension::Period(period) => self
+                .by_period(*period, |_| true)
+                .into_iter()
+                .filter(|dpv| !dpv.value.records.is_empty())
+                .map(|dpv| DimensionGroup {
+                    label: dpv.start.to_string(),
+                    value: dpv.value,
+                })
+                .collect(),
+            Dimension::Binned(field, binning) => self
+                .binned(field, binning)
+                .into_iter()
+                .map(|(bin, view)| DimensionGroup {
+                    label: bin.label,
+                    value: view,
+                })
+                .collect(),
+        }
+    }
+
+    fn group_by_label<F>(&self, label: F) -> Vec<DimensionGroup<'a>>
+    where
+        F: Fn(&AdverseEventRecord) -> String,
+    {
+        sort_map(group_by_owned(self.records.iter().copied(), label))
+            .into_iter()
+            .map(|(label, records)| DimensionGroup {
+                label,
+                value: AdverseEventsView { records },
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,267 @@
+use super::*;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+
+use std::convert::TryInto;
+
+/// Leading bytes of data written by `encrypt`.
+pub const ENCRYPTED_MAGIC: [u8; 8] = *b"AEVTENC\x01";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+// Magic, three Argon2 parameters, salt and nonce
+const HEADER_LEN: usize = ENCRYPTED_MAGIC.len() + 12 + SALT_LEN + NONCE_LEN;
+
+// Largest Argon2 memory cost accepted from a header, far above the default
+// but lower in the browser, where a wasm instance can't grow past 4 GiB
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+#[cfg(target_arch = "wasm32")]
+const MAX_MEMORY_KIB: u32 = 256 * 1024;
+const MAX_ITERATIONS: u32 = 64;
+
+/// Argon2id cost parameters for deriving a key from a passphrase. They're
+/// stored alongside the data, so they can be raised without breaking
+/// anything already encrypted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyDerivation {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KeyDerivation {
+    fn default() -> Self {
+        KeyDerivation {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl KeyDerivation {
+    fn derive_key(&self, passphrase: &str, salt: &[u8]) -> Result<Key, Error> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|_| Error::EncryptionError("invalid key derivation parameters"))?;
+
+        let mut key = Key::default();
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+            .map_err(|_| Error::EncryptionError("could not derive key from passphrase"))?;
+
+        Ok(key)
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&ENCRYPTED_MAGIC)
+}
+
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    encrypt_with(plaintext, passphrase, &KeyDerivation::default())
+}
+
+/// Encrypts with ChaCha20-Poly1305 under a key derived from the passphrase.
+/// The header is authenticated too, so tampering with the parameters is
+/// caught on decryption.
+pub fn encrypt_with(
+    plaintext: &[u8],
+    passphrase: &str,
+    key_derivation: &KeyDerivation,
+) -> Result<Vec<u8>, Error> {
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    getrandom::getrandom(&mut salt)
+        .and_then(|_| getrandom::getrandom(&mut nonce))
+        .map_err(|_| Error::EncryptionError("no source of randomness available"))?;
+
+    let mut data = Vec::with_capacity(HEADER_LEN + plaintext.len() + 16);
+    data.extend_from_slice(&ENCRYPTED_MAGIC);
+    data.extend_from_slice(&key_derivation.memory_kib.to_le_bytes());
+    data.extend_from_slice(&key_derivation.iterations.to_le_bytes());
+    data.extend_from_slice(&key_derivation.parallelism.to_le_bytes());
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+
+    let key = key_derivation.derive_key(passphrase, &salt)?;
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &data,
+            },
+        )
+        .map_err(|_| Error::EncryptionError("encryption failed"))?;
+    data.extend(ciphertext);
+
+    Ok(data)
+}
+
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    if !is_encrypted(data) {
+        return Err(Error::EncryptionError("data is not encrypted"));
+    }
+    if data.len() < HEADER_LEN {
+        return Err(Error::EncryptionError("encrypted data is truncated"));
+    }
+
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    let param = |i: usize| {
+        let start = ENCRYPTED_MAGIC.len() + 4 * i;
+        u32::from_le_bytes(header[start..start + 4].try_into().unwrap())
+    };
+    let key_derivation = KeyDerivation {
+        memory_kib: param(0),
+        iterations: param(1),
+        parallelism: param(2),
+    };
+    // The header isn't authenticated until after the key is derived, so
+    // don't let it ask for unbounded work first
+    if key_derivation.memory_kib > MAX_MEMORY_KIB || key_derivation.iterations > MAX_ITERATIONS {
+        return Err(Error::EncryptionError(
+            "key derivation parameters are too costly",
+        ));
+    }
+    let salt = &header[HEADER_LEN - NONCE_LEN - SALT_LEN..HEADER_LEN - NONCE_LEN];
+    let nonce = &header[HEADER_LEN - NONCE_LEN..];
+
+    let key = key_derivation.derive_key(passphrase, salt)?;
+    ChaCha20Poly1305::new(&key)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+impl AdverseEvents {
+    /// Loads an encrypted dataset, either an original export in any format
+    /// `load` reads or a snapshot.
+    pub fn from_encrypted(data: &[u8], passphrase: &str) -> Result<Self, Error> {
+        Self::load(&decrypt(data, passphrase)?, None)
+    }
+
+    /// The current records, including any joined or looked up attributes,
+    /// encrypted for storage. Snapshots keep every field so they can be
+    /// loaded again, so only a profile that can view them all may take one.
+    pub fn encrypted_snapshot(
+        &self,
+        passphrase: &str,
+        profile: VisibilityProfile,
+    ) -> Result<Vec<u8>, Error> {
+        profile.check_records()?;
+        for field in RecordField::ALL.iter() {
+            profile.check_view(*field)?;
+        }
+
+        let mut csv = Vec::new();
+        self.view().write_csv(&mut csv)?;
+
+        encrypt(&csv, passphrase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    // Far too cheap for real use, but keeps the tests quick
+    const CHEAP: KeyDerivation = KeyDerivation {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn encrypt_round_trips() {
+        let data = encrypt_with(b"Date,MRN\n", "correct horse", &CHEAP).unwrap();
+
+        assert!(is_encrypted(&data));
+        assert_eq!(decrypt(&data, "correct horse").unwrap(), b"Date,MRN\n");
+        assert!(matches!(
+            decrypt(&data, "battery staple"),
+            Err(Error::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn decrypt_rejects_tampering() {
+        let data = encrypt_with(b"Date,MRN\n", "correct horse", &CHEAP).unwrap();
+
+        // The salt in the header is authenticated along with the ciphertext
+        for i in [HEADER_LEN - NONCE_LEN - 1, data.len() - 1] {
+            let mut tampered = data.clone();
+            tampered[i] ^= 1;
+            assert!(matches!(
+                decrypt(&tampered, "correct horse"),
+                Err(Error::DecryptionFailed)
+            ));
+        }
+        assert!(matches!(
+            decrypt(&data[..HEADER_LEN - 1], "correct horse"),
+            Err(Error::EncryptionError(_))
+        ));
+        assert!(matches!(
+            decrypt(b"Date,MRN\n", "correct horse"),
+            Err(Error::EncryptionError(_))
+        ));
+    }
+
+    #[test]
+    fn decrypt_refuses_costly_parameters() {
+        let mut data = encrypt_with(b"Date,MRN\n", "correct horse", &CHEAP).unwrap();
+        let start = ENCRYPTED_MAGIC.len();
+        data[start..start + 4].copy_from_slice(&(MAX_MEMORY_KIB + 1).to_le_bytes());
+
+        assert!(matches!(
+            decrypt(&data, "correct horse"),
+            Err(Error::EncryptionError(_))
+        ));
+    }
+
+    #[test]
+    fn snapshot_round_trips_under_full_phi() {
+        let adverse_events = test_data::adverse_events(vec![TestRecord {
+            date: "03/10/19",
+            patient_name: "Jane Doe",
+            adverse_events: "nausea,pain",
+            an_start: "0730",
+            smoker: true,
+            bmi: 25.5,
+            ..Default::default()
+        }]);
+
+        assert!(matches!(
+            adverse_events.encrypted_snapshot("correct horse", VisibilityProfile::QiReviewer),
+            Err(Error::FieldNotVisible(_))
+        ));
+        assert!(adverse_events
+            .encrypted_snapshot("correct horse", VisibilityProfile::AggregateOnly)
+            .is_err());
+
+        let snapshot = adverse_events
+            .encrypted_snapshot("correct horse", VisibilityProfile::FullPhi)
+            .unwrap();
+        let loaded = AdverseEvents::from_encrypted(&snapshot, "correct horse").unwrap();
+        let (original, loaded) = (&adverse_events.records[0], &loaded.records[0]);
+        assert_eq!(loaded.date, original.date);
+        assert_eq!(loaded.patient_name, original.patient_name);
+        assert_eq!(loaded.adverse_events, original.adverse_events);
+        assert_eq!(loaded.an_start, original.an_start);
+        assert_eq!(loaded.bmi, original.bmi);
+        assert!(matches!(
+            AdverseEvents::load(&snapshot, None),
+            Err(Error::PasswordRequired)
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+use super::*;
+
+const RATE_HOURS: f64 = 100.0;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ExposureRate {
+    pub cases: usize,
+    pub events: usize,
+    pub hours: f64,
+    // Events per 100 anesthesia hours
+    pub rate: f64,
+    pub ci: ConfidenceInterval,
+}
+
+impl ExposureRate {
+    pub fn new(cases: usize, events: usize, hours: f64) -> Self {
+        let ci = poisson_ci(events, Z_95);
+        let per_hours = |count: f64| {
+            if hours > 0.0 {
+                count / hours * RATE_HOURS
+            } else {
+                0.0
+            }
+        };
+
+        ExposureRate {
+            cases,
+            events,
+            hours,
+            rate: per_hours(events as f64),
+            ci: ConfidenceInterval {
+                lower: per_hours(ci.lower),
+                upper: per_hours(ci.upper),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct LabeledExposure {
+    pub label: String,
+    #[serde(flatten)]
+    pub value: ExposureRate,
+}
+
+pub type DatePeriodHours = DatePeriodContainer<f64>;
+pub type DatePeriodExposure = DatePeriodContainer<ExposureRate>;
+
+impl AdverseEventRecord {
+    pub fn anesthesia_duration(&self) -> Duration {
+        let duration = self.an_stop.signed_duration_since(self.an_start);
+
+        // Cases running past midnight stop "before" they start
+        if duration < Duration::zero() {
+            duration + Duration::days(1)
+        } else {
+            duration
+        }
+    }
+
+    pub fn anesthesia_hours(&self) -> f64 {
+        self.anesthesia_duration().num_minutes() as f64 / 60.0
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn anesthesia_hours(&self) -> f64 {
+        self.records
+            .iter()
+            .map(|record| record.anesthesia_hours())
+            .sum()
+    }
+
+    pub fn exposure_rate(&self) -> ExposureRate {
+        ExposureRate::new(
+            self.len(),
+            self.records
+                .iter()
+                .map(|record| record.adverse_events.len())
+                .sum(),
+            self.anesthesia_hours(),
+        )
+    }
+
+    pub fn exposure_by(&self, dimension: &Dimension) -> Vec<LabeledExposure> {
+        self.group_by_dimension(dimension)
+            .into_iter()
+            .map(|group| LabeledExposure {
+                label: group.label,
+                value: group.value.exposure_rate(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    #[test]
+    fn exposure_counts_every_event_over_anesthesia_hours() {
+        let adverse_events = test_data::adverse_events(vec![
+            TestRecord {
+                adverse_events: "nausea,pain",
+                ..Default::default()
+            },
+            // Runs past midnight
+            TestRecord {
+                location: "PACU",
+                an_start: "2300",
+                an_stop: "0100",
+                ..Default::default()
+            },
+            TestRecord {
+                adverse_events: "nausea",
+                ..Default::default()
+            },
+        ]);
+        let view = adverse_events.view();
+
+        assert_eq!(view.records[1].anesthesia_hours(), 2.0);
+        let rate = view.exposure_rate();
+        assert_eq!((rate.cases, rate.events, rate.hours), (3, 3, 4.0));
+        assert!((rate.rate - 75.0).abs() < 1e-12);
+        assert!(rate.ci.lower < 75.0 && rate.ci.upper > 75.0);
+
+        let by_location = view.exposure_by(&Dimension::Location);
+        let pacu = by_location
+            .iter()
+            .find(|exposure| exposure.label == "PACU")
+            .unwrap();
+        assert_eq!((pacu.value.events, pacu.value.rate), (0, 0.0));
+    }
+
+    #[test]
+    fn exposure_without_hours_is_zero() {
+        let rate = ExposureRate::new(2, 1, 0.0);
+        assert_eq!(rate.rate, 0.0);
+        assert_eq!((rate.ci.lower, rate.ci.upper), (0.0, 0.0));
+    }
+}
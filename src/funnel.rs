@@ -0,0 +1,200 @@
+use super::*;
+
+const WARNING_CONFIDENCE: f64 = 0.95;
+const CONTROL_CONFIDENCE: f64 = 0.998;
+const LIMIT_POINTS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum FunnelFlag {
+    Within,
+    AboveWarning,
+    AboveControl,
+    BelowWarning,
+    BelowControl,
+}
+
+impl fmt::Display for FunnelFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FunnelFlag::Within => write!(f, "Within limits"),
+            FunnelFlag::AboveWarning => write!(f, "Above 95% limit"),
+            FunnelFlag::AboveControl => write!(f, "Above 99.8% limit"),
+            FunnelFlag::BelowWarning => write!(f, "Below 95% limit"),
+            FunnelFlag::BelowControl => write!(f, "Below 99.8% limit"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct FunnelLimits {
+    pub cases: usize,
+    pub warning: ConfidenceInterval,
+    pub control: ConfidenceInterval,
+}
+
+impl FunnelLimits {
+    pub fn new(overall_rate: f64, cases: usize) -> Self {
+        FunnelLimits {
+            cases,
+            warning: binomial_limits(overall_rate, cases, z_for_confidence(WARNING_CONFIDENCE)),
+            control: binomial_limits(overall_rate, cases, z_for_confidence(CONTROL_CONFIDENCE)),
+        }
+    }
+
+    pub fn flag(&self, rate: f64) -> FunnelFlag {
+        if rate > self.control.upper {
+            FunnelFlag::AboveControl
+        } else if rate > self.warning.upper {
+            FunnelFlag::AboveWarning
+        } else if rate < self.control.lower {
+            FunnelFlag::BelowControl
+        } else if rate < self.warning.lower {
+            FunnelFlag::BelowWarning
+        } else {
+            FunnelFlag::Within
+        }
+    }
+}
+
+fn binomial_limits(rate: f64, cases: usize, z: f64) -> ConfidenceInterval {
+    let spread = z * (rate * (1.0 - rate) / cases as f64).sqrt();
+    ConfidenceInterval {
+        lower: (rate - spread).max(0.0),
+        upper: (rate + spread).min(1.0),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct FunnelPoint {
+    pub label: String,
+    pub cases: usize,
+    pub events: usize,
+    pub rate: f64,
+    pub limits: FunnelLimits,
+    pub flag: FunnelFlag,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct Funnel {
+    pub outcome: Outcome,
+    pub cases: usize,
+    pub events: usize,
+    pub overall_rate: f64,
+    pub points: Vec<FunnelPoint>,
+    // Limits sampled across the range of group volumes for drawing the funnel
+    pub limits: Vec<FunnelLimits>,
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn funnel(&self, dimension: &Dimension, outcome: Outcome) -> Funnel {
+        let cases = self.len();
+        let events = self.count_outcome(&outcome);
+        let overall_rate = if cases == 0 {
+            0.0
+        } else {
+            events as f64 / cases as f64
+        };
+
+        let points: Vec<FunnelPoint> = self
+            .group_by_dimension(dimension)
+            .into_iter()
+            .filter(|group| !group.value.records.is_empty())
+            .map(|group| {
+                let cases = group.value.len();
+                let events = group.value.count_outcome(&outcome);
+                let rate = events as f64 / cases as f64;
+                let limits = FunnelLimits::new(overall_rate, cases);
+                let flag = limits.flag(rate);
+
+                FunnelPoint {
+                    label: group.label,
+                    cases,
+                    events,
+                    rate,
+                    limits,
+                    flag,
+                }
+            })
+            .collect();
+
+        let max_cases = points.iter().map(|point| point.cases).max().unwrap_or(0);
+        let mut volumes: Vec<usize> = (0..=LIMIT_POINTS)
+            .map(|i| {
+                // Geometric spacing keeps the curve smooth where the limits are widest
+                (max_cases as f64)
+                    .powf(i as f64 / LIMIT_POINTS as f64)
+                    .round() as usize
+            })
+            .filter(|&n| n > 0 && n <= max_cases)
+            .collect();
+        volumes.dedup();
+
+        Funnel {
+            outcome,
+            cases,
+            events,
+            overall_rate,
+            points,
+            limits: volumes
+                .into_iter()
+                .map(|n| FunnelLimits::new(overall_rate, n))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    #[test]
+    fn funnel_flags_groups_outside_the_limits() {
+        // (location, events) for 100 cases each, 20% overall
+        let groups = [("A", 10), ("B", 20), ("C", 40), ("D", 10)];
+        let adverse_events =
+            test_data::adverse_events(groups.iter().flat_map(|&(location, events)| {
+                (0..100).map(move |i| TestRecord {
+                    location,
+                    ..TestRecord::with_event(i < events)
+                })
+            }));
+        let funnel = adverse_events
+            .view()
+            .funnel(&Dimension::Location, Outcome::AnyEvent);
+
+        assert_eq!((funnel.cases, funnel.events), (400, 80));
+        assert!((funnel.overall_rate - 0.2).abs() < 1e-12);
+        let flags: Vec<FunnelFlag> = funnel.points.iter().map(|point| point.flag).collect();
+        assert_eq!(
+            flags,
+            [
+                FunnelFlag::BelowWarning,
+                FunnelFlag::Within,
+                FunnelFlag::AboveControl,
+                FunnelFlag::BelowWarning
+            ]
+        );
+
+        let volumes: Vec<usize> = funnel.limits.iter().map(|limits| limits.cases).collect();
+        assert_eq!((volumes[0], *volumes.last().unwrap()), (1, 100));
+        assert!(volumes.windows(2).all(|pair| pair[0] < pair[1]));
+        // The funnel narrows as volume grows
+        let first = &funnel.limits[0].warning;
+        let last = &funnel.limits[funnel.limits.len() - 1].warning;
+        assert!(last.upper - last.lower < first.upper - first.lower);
+    }
+
+    #[test]
+    fn limits_stay_within_zero_and_one() {
+        let limits = FunnelLimits::new(0.02, 5);
+        assert_eq!(limits.control.lower, 0.0);
+        assert!(limits.control.upper <= 1.0);
+        assert_eq!(limits.flag(0.0), FunnelFlag::Within);
+        assert_eq!(limits.flag(1.0), FunnelFlag::AboveControl);
+    }
+}
@@ -0,0 +1,299 @@
+use super::*;
+
+use calamine::{Data, Reader, Xlsx};
+use flate2::read::MultiGzDecoder;
+use ruzstd::StreamingDecoder;
+
+use std::io::{Cursor, SeekFrom};
+
+/// Leading bytes of a gzip stream.
+pub const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+/// Leading bytes of a Zstandard frame.
+pub const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+/// Largest decompressed input accepted, so a small archive can't expand to
+/// fill the browser's memory.
+pub const MAX_DECOMPRESSED_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum InputFormat {
+    // Data from `encrypt` or an encrypted snapshot
+    Encrypted,
+    Zip,
+    Xlsx,
+    Gzip,
+    Zstd,
+    Csv,
+    Tsv,
+}
+
+/// Recognizes the format of the data from its content, falling back to
+/// CSV for anything that isn't one of the binary formats.
+pub fn detect_format(data: &[u8]) -> InputFormat {
+    if is_encrypted(data) {
+        InputFormat::Encrypted
+    } else if data.starts_with(ZIP_MAGIC) {
+        // Workbooks are ZIP archives too, told apart by their contents
+        match ZipArchive::new(Cursor::new(data)) {
+            Ok(archive) if archive.file_names().any(|name| name == "xl/workbook.xml") => {
+                InputFormat::Xlsx
+            }
+            _ => InputFormat::Zip,
+        }
+    } else if data.starts_with(GZIP_MAGIC) {
+        InputFormat::Gzip
+    } else if data.starts_with(ZSTD_MAGIC) {
+        InputFormat::Zstd
+    } else {
+        let header = data.split(|byte| *byte == b'\n').next().unwrap_or_default();
+        let count = |delimiter: u8| header.iter().filter(|byte| **byte == delimiter).count();
+
+        if count(b'\t') > count(b',') {
+            InputFormat::Tsv
+        } else {
+            InputFormat::Csv
+        }
+    }
+}
+
+/// Reads decompressed data, refusing anything over `MAX_DECOMPRESSED_SIZE`.
+pub(crate) fn read_decompressed<R>(reader: R) -> Result<Vec<u8>, Error>
+where
+    R: Read,
+{
+    let mut decompressed = Vec::new();
+    reader
+        .take(MAX_DECOMPRESSED_SIZE + 1)
+        .read_to_end(&mut decompressed)?;
+
+    if decompressed.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        Err(Error::InputTooLarge)
+    } else {
+        Ok(decompressed)
+    }
+}
+
+/// Text for a spreadsheet cell as it would appear in an exported CSV.
+fn cell_text(cell: &Data, header: &str) -> String {
+    let time_column = RecordField::AnStart.column() == Some(header)
+        || RecordField::AnStop.column() == Some(header);
+
+    match cell {
+        Data::DateTime(datetime) if datetime.is_datetime() => match datetime.as_datetime() {
+            // Without a date part it's only a time of day
+            Some(datetime_value) if time_column || datetime.as_f64() < 1.0 => {
+                datetime_value.format("%H%M").to_string()
+            }
+            Some(datetime_value) => datetime_value.format("%m/%d/%y").to_string(),
+            None => cell.to_string(),
+        },
+        Data::Float(value) if value.fract() == 0.0 => cell_text(&Data::Int(*value as i64), header),
+        // Times entered as numbers lose their leading zeroes
+        Data::Int(value) if time_column => format!("{:04}", value),
+        _ => cell.to_string(),
+    }
+}
+
+impl AdverseEvents {
+    /// Reads the first worksheet of a workbook laid out like the CSV export.
+    pub fn from_xlsx<R>(mut reader: R) -> Result<Self, Error>
+    where
+        R: Read + Seek,
+    {
+        // calamine inflates whole parts of the workbook at once, so the sizes
+        // they declare are checked first
+        let mut archive = ZipArchive::new(&mut reader)?;
+        let mut size: u64 = 0;
+        for i in 0..archive.len() {
+            size = size.saturating_add(archive.by_index_raw(i)?.size());
+        }
+        if size > MAX_DECOMPRESSED_SIZE {
+            return Err(Error::InputTooLarge);
+        }
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut workbook: Xlsx<R> = Xlsx::new(reader)?;
+        let range = workbook
+            .worksheet_range_at(0)
+            .ok_or(Error::InsufficientData("the workbook has no worksheets"))??;
+
+        let mut rows = range.rows();
+        let headers: csv::StringRecord = rows
+            .next()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .unwrap_or_default();
+        let rows: Vec<csv::StringRecord> = rows
+            .map(|row| {
+                row.iter()
+                    .zip(headers.iter())
+                    .map(|(cell, header)| cell_text(cell, header))
+                    .collect()
+            })
+            .collect();
+
+        Self::from_rows(&headers, &rows)
+    }
+
+    /// Loads records from data in any supported format, recognized by its
+    /// content. The password is used for encrypted data and protected ZIP
+    /// archives.
+    pub fn load(data: &[u8], password: Option<&str>) -> Result<Self, Error> {
+        Self::load_nested(data, password, false)
+    }
+
+    /// Like `load`, but compressed data inside compressed data is refused,
+    /// since each layer can multiply the size.
+    fn load_nested(data: &[u8], password: Option<&str>, decompressed: bool) -> Result<Self, Error> {
+        match detect_format(data) {
+            InputFormat::Encrypted => Self::load_nested(
+                &decrypt(data, password.ok_or(Error::PasswordRequired)?)?,
+                None,
+                decompressed,
+            ),
+            InputFormat::Zip => Self::from_zip(Cursor::new(data), password.map(str::as_bytes)),
+            InputFormat::Xlsx => Self::from_xlsx(Cursor::new(data)),
+            InputFormat::Gzip | InputFormat::Zstd if decompressed => Err(Error::InsufficientData(
+                "compressed data inside compressed data isn't supported",
+            )),
+            InputFormat::Gzip => Self::load_nested(
+                &read_decompressed(MultiGzDecoder::new(data))?,
+                password,
+                true,
+            ),
+            InputFormat::Zstd => Self::load_nested(
+                &read_decompressed(
+                    StreamingDecoder::new(data)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+                )?,
+                password,
+                true,
+            ),
+            InputFormat::Csv => Self::from_csv_reader(data),
+            InputFormat::Tsv => Self::from_delimited_reader(data, b'\t'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use calamine::{ExcelDateTime, ExcelDateTimeType};
+    use flate2::{write::GzEncoder, Compression};
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    use std::io::Write;
+
+    // "Date,MRN" stored in a ZIP archive with ZipCrypto and the password
+    // "secret"
+    const PROTECTED_ZIP: [u8; 197] = [
+        0x50, 0x4b, 0x03, 0x04, 0x0a, 0x00, 0x09, 0x00, 0x00, 0x00, 0x04, 0x3b, 0x53, 0x5d, 0xff,
+        0xbf, 0x70, 0xb5, 0x15, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x05, 0x00, 0x1c, 0x00,
+        0x61, 0x2e, 0x63, 0x73, 0x76, 0x55, 0x54, 0x09, 0x00, 0x03, 0x97, 0xc5, 0xd5, 0x6a, 0x97,
+        0xc5, 0xd5, 0x6a, 0x75, 0x78, 0x0b, 0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00,
+        0x00, 0x00, 0x00, 0x10, 0xca, 0xdd, 0x94, 0x1d, 0x55, 0xd2, 0x31, 0xa4, 0xf6, 0x99, 0xab,
+        0xa9, 0xbe, 0xc8, 0xb0, 0xde, 0xb5, 0xa0, 0x73, 0xdd, 0x50, 0x4b, 0x07, 0x08, 0xff, 0xbf,
+        0x70, 0xb5, 0x15, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x50, 0x4b, 0x01, 0x02, 0x1e,
+        0x03, 0x0a, 0x00, 0x09, 0x00, 0x00, 0x00, 0x04, 0x3b, 0x53, 0x5d, 0xff, 0xbf, 0x70, 0xb5,
+        0x15, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x05, 0x00, 0x18, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x00, 0x00, 0x61, 0x2e, 0x63, 0x73,
+        0x76, 0x55, 0x54, 0x05, 0x00, 0x03, 0x97, 0xc5, 0xd5, 0x6a, 0x75, 0x78, 0x0b, 0x00, 0x01,
+        0x04, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x50, 0x4b, 0x05, 0x06, 0x00,
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x4b, 0x00, 0x00, 0x00, 0x64, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ];
+    // Where the stored CSV starts, after the local header and the 12 byte
+    // encryption header
+    const PROTECTED_CSV_OFFSET: usize = 75;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn cell_text_writes_times_as_hhmm() {
+        let datetime = |value| {
+            Data::DateTime(ExcelDateTime::new(
+                value,
+                ExcelDateTimeType::DateTime,
+                false,
+            ))
+        };
+
+        // 2019-03-10 07:30
+        assert_eq!(cell_text(&datetime(43534.3125), "An Start"), "0730");
+        assert_eq!(cell_text(&datetime(43534.3125), "Date"), "03/10/19");
+        assert_eq!(cell_text(&datetime(0.3125), "Location"), "0730");
+        assert_eq!(cell_text(&Data::Float(730.0), "An Stop"), "0730");
+        assert_eq!(cell_text(&Data::Float(730.0), "ASA"), "730");
+    }
+
+    #[test]
+    fn load_allows_one_level_of_compression() {
+        let csv = b"Date,MRN\n";
+
+        assert_eq!(detect_format(&gzip(csv)), InputFormat::Gzip);
+        assert!(AdverseEvents::load(&gzip(csv), None).is_ok());
+        assert!(matches!(
+            AdverseEvents::load(&gzip(&gzip(csv)), None),
+            Err(Error::InsufficientData(_))
+        ));
+    }
+
+    #[test]
+    fn from_xlsx_refuses_oversized_parts() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("xl/workbook.xml", options).unwrap();
+        zip.write_all(b"<workbook/>").unwrap();
+        let mut data = zip.finish().unwrap().into_inner();
+
+        // Claim a much larger uncompressed size in the central directory
+        let central = data
+            .windows(4)
+            .position(|window| window == b"PK\x01\x02")
+            .unwrap();
+        data[central + 24..central + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(
+            AdverseEvents::from_xlsx(Cursor::new(data)),
+            Err(Error::InputTooLarge)
+        ));
+    }
+
+    #[test]
+    fn detect_format_tells_tsv_from_csv() {
+        assert_eq!(detect_format(b"Date\tMRN\tASA\n"), InputFormat::Tsv);
+        assert_eq!(detect_format(b"Date,MRN,ASA\n"), InputFormat::Csv);
+    }
+
+    #[test]
+    fn protected_zip_reports_wrong_passwords() {
+        assert!(AdverseEvents::load(&PROTECTED_ZIP, Some("secret")).is_ok());
+        assert!(matches!(
+            AdverseEvents::load(&PROTECTED_ZIP, Some("wrong")),
+            Err(Error::WrongPassword)
+        ));
+        assert!(matches!(
+            AdverseEvents::load(&PROTECTED_ZIP, None),
+            Err(Error::PasswordRequired)
+        ));
+    }
+
+    #[test]
+    fn corrupt_protected_zip_is_not_a_wrong_password() {
+        let mut corrupt = PROTECTED_ZIP;
+        corrupt[PROTECTED_CSV_OFFSET + 2] ^= 1;
+
+        assert!(matches!(
+            AdverseEvents::load(&corrupt, Some("secret")),
+            Err(Error::IoError(_))
+        ));
+        assert!(matches!(
+            AdverseEvents::load(&PROTECTED_ZIP[..150], Some("secret")),
+            Err(Error::DecompressError(_))
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use calamine::XlsxError;
 use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Weekday};
 use csv;
 #[cfg(feature = "gen-fake")]
@@ -13,52 +14,155 @@ use zip::{result::ZipError, ZipArchive};
 
 use deserialize::{
     comma_separated, hhmm_time, line_separated, mm_dd_yy_date, non_null_bool, nullable_yes_no_bool,
-    FromCsv,
 };
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     convert::From,
     fmt, hash,
     io::{self, Read, Seek},
 };
 
+mod binning;
 mod breakdown;
+mod columns;
+mod compare;
+mod concurrency;
+mod cooccurrence;
+mod deidentify;
+mod denominators;
+mod dimension;
+mod encryption;
+mod exposure;
+mod funnel;
+mod input;
+mod lookup;
+mod outcome;
+mod output;
+mod patients;
+mod risk;
+mod shrinkage;
+mod staff;
+mod standardization;
+mod stats;
+mod summary;
+mod supplement;
+mod suppression;
 mod time_period;
+#[cfg(test)]
+mod test_data;
+mod visibility;
 
+pub use binning::*;
 pub use breakdown::*;
+pub use columns::*;
+pub use compare::*;
+pub use concurrency::*;
+pub use cooccurrence::*;
+pub use deidentify::*;
+pub use denominators::*;
+pub use dimension::*;
+pub use encryption::*;
+pub use exposure::*;
+pub use funnel::*;
+pub use input::*;
+pub use lookup::*;
+pub use outcome::*;
+pub use output::*;
+pub use patients::*;
+pub use risk::*;
+pub use shrinkage::*;
+pub use staff::*;
+pub use standardization::*;
+pub use stats::*;
+pub use summary::*;
+pub use supplement::*;
+pub use suppression::*;
 pub use time_period::*;
+pub use visibility::*;
 
 pub struct AdverseEvents {
     pub records: Vec<AdverseEventRecord>,
+    pub denominators: Option<Denominators>,
 }
 
+/// Leading bytes of a ZIP archive.
+pub const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
 impl AdverseEvents {
     pub fn new() -> Self {
         AdverseEvents {
             records: Vec::new(),
+            denominators: None,
         }
     }
 
-    pub fn from_zip<R>(data: R) -> Result<Self, Error>
+    /// Reads the CSV inside a ZIP archive, decrypting it with `password` if
+    /// it's protected with either ZipCrypto or AES.
+    pub fn from_zip<R>(data: R, password: Option<&[u8]>) -> Result<Self, Error>
     where
         R: Read + Seek,
     {
         let mut archive = ZipArchive::new(data)?;
-        let csv = archive.by_index(0)?;
-        if csv.is_file() && csv.name().ends_with(".csv") {
-            Self::from_csv_reader(csv)
-        } else {
-            Err(Error::DecompressError(ZipError::FileNotFound))
+        let mut file = match password {
+            Some(password) => archive
+                .by_index_decrypt(0, password)?
+                .map_err(|_| Error::WrongPassword)?,
+            None => archive.by_index(0).map_err(|e| match e {
+                ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) => {
+                    Error::PasswordRequired
+                }
+                e => Error::DecompressError(e),
+            })?,
+        };
+        if !file.is_file() || !file.name().ends_with(".csv") {
+            return Err(Error::DecompressError(ZipError::FileNotFound));
         }
+
+        // Only a byte or two of the password is checked up front, so rarely
+        // a wrong one fails here instead, indistinguishable from corruption
+        let csv = read_decompressed(&mut file)?;
+
+        Self::from_csv_reader(csv.as_slice())
     }
 
     pub fn from_csv_reader<R>(reader: R) -> Result<Self, Error>
     where
         R: Read,
     {
-        let records = AdverseEventRecord::from_csv_reader(reader)?;
-        Ok(AdverseEvents { records })
+        Self::from_delimited_reader(reader, b',')
+    }
+
+    pub fn from_delimited_reader<R>(reader: R, delimiter: u8) -> Result<Self, Error>
+    where
+        R: Read,
+    {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .from_reader(reader);
+        let headers = reader.headers()?.clone();
+        let rows = reader.records().collect::<Result<Vec<_>, _>>()?;
+
+        Self::from_rows(&headers, &rows)
+    }
+
+    fn from_rows(headers: &csv::StringRecord, rows: &[csv::StringRecord]) -> Result<Self, Error> {
+        let mut records = rows
+            .iter()
+            .map(|row| row.deserialize(Some(headers)))
+            .collect::<Result<Vec<AdverseEventRecord>, _>>()?;
+        capture_extra_columns(headers, rows, &mut records);
+
+        Ok(Self::from_records(records))
+    }
+
+    pub fn from_records(mut records: Vec<AdverseEventRecord>) -> Self {
+        compute_concurrency(&mut records);
+        compute_prior_events(&mut records);
+        AdverseEvents {
+            records,
+            denominators: None,
+        }
     }
 
     pub fn view(&self) -> AdverseEventsView {
@@ -139,12 +243,7 @@ impl<'a> AdverseEventsView<'a> {
     }
 
     pub fn with_staff(&self, staff_name: &str) -> Self {
-        self.with_filter(|record| {
-            record
-                .anesthesia_staff
-                .iter()
-                .any(|name| name == staff_name)
-        })
+        self.with_filter(|record| record.staff().contains(&staff_name))
     }
 
     pub fn with_procedure(&self, proc_name: &str) -> Self {
@@ -171,12 +270,11 @@ impl<'a> AdverseEventsView<'a> {
     where
         F: FnMut(&&&AdverseEventRecord) -> bool,
     {
+        let records = self.records.iter().filter(filter).copied();
+
         match period {
             Period::Day => {
-                let mut by_date =
-                    group_by_owned(self.records.iter().filter(filter).copied(), |record| {
-                        record.date
-                    });
+                let mut by_date = group_by_owned(records, |record| record.date);
 
                 let min: Option<NaiveDate> = by_date.keys().copied().min();
                 let max: Option<NaiveDate> = by_date.keys().copied().max();
@@ -203,8 +301,8 @@ impl<'a> AdverseEventsView<'a> {
                 }
             }
             Period::Week => {
-                let mut by_week = group_by_owned(self.records.iter().copied(), |record| {
-                    (record.date.year(), record.date.iso_week().week())
+                let mut by_week = group_by_owned(records, |record| {
+                    (record.date.iso_week().year(), record.date.iso_week().week())
                 });
 
                 let dates: Vec<_> = by_week
@@ -221,14 +319,14 @@ impl<'a> AdverseEventsView<'a> {
 
                     while date <= *max {
                         let records = by_week
-                            .remove(&(date.year(), date.iso_week().week()))
+                            .remove(&(date.iso_week().year(), date.iso_week().week()))
                             .unwrap_or_default();
 
                         ret.push(DatePeriodView {
                             period,
                             start: date,
                             end: NaiveDate::from_isoywd(
-                                date.year(),
+                                date.iso_week().year(),
                                 date.iso_week().week(),
                                 Weekday::Sun,
                             ),
@@ -244,9 +342,8 @@ impl<'a> AdverseEventsView<'a> {
                 }
             }
             Period::Month => {
-                let mut by_month = group_by_owned(self.records.iter().copied(), |record| {
-                    (record.date.year(), record.date.month())
-                });
+                let mut by_month =
+                    group_by_owned(records, |record| (record.date.year(), record.date.month()));
                 let dates: Vec<_> = by_month
                     .keys()
                     .map(|(year, month)| NaiveDate::from_ymd(*year, *month, 1))
@@ -265,9 +362,9 @@ impl<'a> AdverseEventsView<'a> {
                             .unwrap_or_default();
 
                         let next_date = if date.month() == 12 {
-                            NaiveDate::from_ymd(date.year(), date.month() + 1, 1)
-                        } else {
                             NaiveDate::from_ymd(date.year() + 1, 1, 1)
+                        } else {
+                            NaiveDate::from_ymd(date.year(), date.month() + 1, 1)
                         };
 
                         ret.push(DatePeriodView {
@@ -285,8 +382,7 @@ impl<'a> AdverseEventsView<'a> {
                 }
             }
             Period::Year => {
-                let mut by_year =
-                    group_by_owned(self.records.iter().copied(), |record| record.date.year());
+                let mut by_year = group_by_owned(records, |record| record.date.year());
 
                 let min: Option<i32> = by_year.keys().copied().min();
                 let max: Option<i32> = by_year.keys().copied().max();
@@ -315,82 +411,6 @@ impl<'a> AdverseEventsView<'a> {
             }
         }
     }
-
-    pub fn get_breakdown(&self, breakdown_type: BreakdownType) -> Vec<LabeledCount> {
-        match breakdown_type {
-            BreakdownType::WithComplications => {
-                vec![
-                    LabeledCount {
-                        label: "With complications".to_string(),
-                        value: self.count(|record| record.complications == Some(true)),
-                    },
-                    LabeledCount {
-                        label: "Without complications".to_string(),
-                        value: self.count(|record| record.complications == Some(false)),
-                    },
-                    LabeledCount {
-                        label: "Unspecified complications".to_string(),
-                        value: self.count(|record| record.complications.is_none()),
-                    },
-                ]
-            }
-            BreakdownType::WithEvent => {
-                vec![
-                    LabeledCount {
-                        label: "With event".to_string(),
-                        value: self.count(|record| !record.adverse_events.is_empty()),
-                    },
-                    LabeledCount {
-                        label: "Without event".to_string(),
-                        value: self.count(|record| record.adverse_events.is_empty()),
-                    },
-                ]
-            }
-            BreakdownType::PatientAge => {
-                let range_size = 10;
-                sort_map(group_by_owned(
-                    self.records.iter().filter(|r| !r.adverse_events.is_empty()),
-                    |record| record.age - (record.age % range_size),
-                ))
-                .into_iter()
-                .map(|(range_start, records)| LabeledCount {
-                    label: format!("{} - {}", range_start, range_start + range_size - 1),
-                    value: records.len(),
-                })
-                .collect()
-            }
-            BreakdownType::PatientBmi => {
-                let range_size = 5;
-                sort_map(group_by_owned(
-                    self.records.iter().filter(|r| !r.adverse_events.is_empty()),
-                    |record| {
-                        let bmi = record.bmi as usize;
-                        bmi - (bmi % range_size)
-                    },
-                ))
-                .into_iter()
-                .map(|(range_start, records)| LabeledCount {
-                    label: format!("{} - {}", range_start, range_start + range_size - 1),
-                    value: records.len(),
-                })
-                .collect()
-            }
-            BreakdownType::PatientSmoker => {
-                vec![
-                    LabeledCount {
-                        label: "Smoker".to_string(),
-                        value: self
-                            .count(|record| !record.adverse_events.is_empty() && record.smoker),
-                    },
-                    LabeledCount {
-                        label: "Non-smoker".to_string(),
-                        value: self
-                            .count(|record| !record.adverse_events.is_empty() && !record.smoker),
-                    },
-                ]
-            }
-        }
-    }
 }
 
 pub fn sort_map<K, V>(map: HashMap<K, V>) -> Vec<(K, V)>
@@ -460,7 +480,7 @@ mod gen_fake {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "gen-fake", derive(Dummy))]
 #[serde(rename_all(serialize = "camelCase"))]
 pub struct AdverseEventRecord {
@@ -553,15 +573,34 @@ pub struct AdverseEventRecord {
     #[serde(rename(deserialize = "BMI"))]
     #[cfg_attr(feature = "gen-fake", dummy(faker = "10.0..40.0"))]
     pub bmi: f64,
-}
 
-impl FromCsv for AdverseEventRecord {}
+    #[serde(skip)]
+    pub concurrency: Concurrency,
+    #[serde(skip)]
+    pub days_since_prior_event: Option<i64>,
+    // Attributes attached from lookup tables and supplementary extracts
+    #[serde(skip_deserializing, skip_serializing_if = "BTreeMap::is_empty")]
+    #[cfg_attr(feature = "gen-fake", dummy(default))]
+    pub attributes: BTreeMap<String, AttributeValue>,
+}
 
 #[derive(Debug)]
 pub enum Error {
     DecompressError(ZipError),
+    SpreadsheetError(XlsxError),
     CsvError(csv::Error),
     IoError(io::Error),
+    InsufficientData(&'static str),
+    InvalidConfig(&'static str),
+    FieldNotVisible(RecordField),
+    EncryptionError(&'static str),
+    // Wrong passphrase, or the data was corrupted or tampered with
+    DecryptionFailed,
+    // The archive or data is password protected and no password was given
+    PasswordRequired,
+    WrongPassword,
+    // Decompressed input over `MAX_DECOMPRESSED_SIZE`
+    InputTooLarge,
     ParseError {
         type_name: &'static str,
         received: String,
@@ -574,6 +613,12 @@ impl From<ZipError> for Error {
     }
 }
 
+impl From<XlsxError> for Error {
+    fn from(e: XlsxError) -> Self {
+        Error::SpreadsheetError(e)
+    }
+}
+
 impl From<csv::Error> for Error {
     fn from(e: csv::Error) -> Self {
         Error::CsvError(e)
@@ -595,6 +640,27 @@ impl fmt::Display for Error {
             } => {
                 write!(f, "ParseError: invalid {}: {}", type_name, received)
             }
+            Error::InsufficientData(reason) => write!(f, "InsufficientData: {}", reason),
+            Error::InvalidConfig(reason) => write!(f, "InvalidConfig: {}", reason),
+            Error::EncryptionError(reason) => write!(f, "EncryptionError: {}", reason),
+            Error::DecryptionFailed => write!(
+                f,
+                "DecryptionFailed: wrong passphrase, or the data is corrupted"
+            ),
+            Error::PasswordRequired => write!(f, "PasswordRequired: the data is encrypted"),
+            Error::WrongPassword => write!(f, "WrongPassword: incorrect password for the archive"),
+            Error::InputTooLarge => write!(
+                f,
+                "InputTooLarge: decompressed input is over {} bytes",
+                MAX_DECOMPRESSED_SIZE
+            ),
+            Error::FieldNotVisible(field) => {
+                write!(
+                    f,
+                    "FieldNotVisible: {} is not visible to this profile",
+                    field.key()
+                )
+            }
             err => write!(f, "{:?}", err),
         }
     }
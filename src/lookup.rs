@@ -0,0 +1,306 @@
+use super::*;
+
+use regex::{Regex, RegexBuilder};
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum LookupField {
+    Procedure,
+    Diagnosis,
+    Location,
+}
+
+impl LookupField {
+    pub fn value<'r>(&self, record: &'r AdverseEventRecord) -> &'r str {
+        match self {
+            LookupField::Procedure => &record.procedure,
+            LookupField::Diagnosis => &record.diagnosis,
+            LookupField::Location => &record.location,
+        }
+    }
+}
+
+impl FromStr for LookupField {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "procedure" => Ok(LookupField::Procedure),
+            "diagnosis" => Ok(LookupField::Diagnosis),
+            "location" => Ok(LookupField::Location),
+            x => Err(crate::Error::ParseError {
+                type_name: "LookupField",
+                received: x.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum LookupPattern {
+    Exact(String),
+    // Globs (`*` and `?`) are compiled to anchored regexes, and like regexes
+    // match regardless of case
+    Regex(Regex),
+}
+
+impl LookupPattern {
+    fn parse(pattern: &str) -> Result<Self, Error> {
+        let err = || Error::ParseError {
+            type_name: "LookupPattern",
+            received: pattern.to_string(),
+        };
+
+        if pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/') {
+            RegexBuilder::new(&pattern[1..pattern.len() - 1])
+                .case_insensitive(true)
+                .build()
+                .map(LookupPattern::Regex)
+                .map_err(|_| err())
+        } else if pattern.contains(['*', '?']) {
+            let glob = regex::escape(pattern)
+                .replace("\\*", ".*")
+                .replace("\\?", ".");
+            RegexBuilder::new(&format!("^{}$", glob))
+                .case_insensitive(true)
+                .build()
+                .map(LookupPattern::Regex)
+                .map_err(|_| err())
+        } else {
+            Ok(LookupPattern::Exact(pattern.to_lowercase()))
+        }
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            LookupPattern::Exact(exact) => value.trim().to_lowercase() == *exact,
+            LookupPattern::Regex(regex) => regex.is_match(value.trim()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LookupRule {
+    pattern: LookupPattern,
+    attributes: Vec<(String, String)>,
+}
+
+/// Reference table mapping raw values of a field to grouping attributes.
+///
+/// The first column of the CSV holds the value to match: an exact value, a
+/// glob using `*` and `?`, or a regex wrapped in slashes, all matched
+/// case-insensitively. Every other column is an attribute, named by its header. Rules
+/// are tried in file order and the first match wins.
+#[derive(Debug)]
+pub struct LookupTable {
+    pub field: LookupField,
+    pub attribute_names: Vec<String>,
+    rules: Vec<LookupRule>,
+}
+
+impl LookupTable {
+    pub fn from_csv_reader<R>(field: LookupField, reader: R) -> Result<Self, Error>
+    where
+        R: Read,
+    {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+
+        let attribute_names: Vec<String> = reader
+            .headers()?
+            .iter()
+            .skip(1)
+            .map(|name| name.trim().to_string())
+            .collect();
+        if attribute_names.is_empty() {
+            return Err(Error::InsufficientData(
+                "lookup table has no attribute columns",
+            ));
+        }
+
+        let mut rules = Vec::new();
+        for row in reader.records() {
+            let row = row?;
+            let pattern = match row.get(0).map(str::trim) {
+                Some(pattern) if !pattern.is_empty() => pattern,
+                _ => continue,
+            };
+
+            rules.push(LookupRule {
+                pattern: LookupPattern::parse(pattern)?,
+                attributes: attribute_names
+                    .iter()
+                    .zip(row.iter().skip(1))
+                    .map(|(name, value)| (name.clone(), value.trim().to_string()))
+                    .filter(|(_, value)| !value.is_empty())
+                    .collect(),
+            });
+        }
+
+        Ok(LookupTable {
+            field,
+            attribute_names,
+            rules,
+        })
+    }
+
+    fn lookup(&self, value: &str) -> Option<&LookupRule> {
+        self.rules.iter().find(|rule| rule.pattern.matches(value))
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct UnmatchedValue {
+    pub value: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct LookupReport {
+    pub field: LookupField,
+    pub matched: usize,
+    // Most frequent first
+    pub unmatched: Vec<UnmatchedValue>,
+    // Attributes that replaced values already on records, such as an extra
+    // column or a joined supplement with the same name
+    pub overwritten: Vec<String>,
+}
+
+impl AdverseEvents {
+    pub fn apply_lookup(&mut self, table: &LookupTable) -> LookupReport {
+        let mut matched = 0;
+        let mut unmatched: HashMap<String, usize> = HashMap::new();
+        let mut overwritten: BTreeSet<String> = BTreeSet::new();
+
+        for record in &mut self.records {
+            match table.lookup(table.field.value(record)) {
+                Some(rule) => {
+                    matched += 1;
+                    for (name, value) in &rule.attributes {
+                        let previous = record
+                            .attributes
+                            .insert(name.clone(), AttributeValue::Text(value.clone()));
+                        if previous.is_some() {
+                            overwritten.insert(name.clone());
+                        }
+                    }
+                }
+                None => {
+                    *unmatched
+                        .entry(table.field.value(record).trim().to_string())
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        let mut unmatched: Vec<UnmatchedValue> = unmatched
+            .into_iter()
+            .map(|(value, count)| UnmatchedValue { value, count })
+            .collect();
+        unmatched.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+
+        LookupReport {
+            field: table.field,
+            matched,
+            unmatched,
+            overwritten: overwritten.into_iter().collect(),
+        }
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn with_attribute(&self, name: &str, value: &str) -> Self {
+        self.with_filter(|record| {
+            matches!(record.attributes.get(name), Some(attribute) if attribute.to_string() == value)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    fn table(csv: &str) -> LookupTable {
+        LookupTable::from_csv_reader(LookupField::Location, csv.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn patterns_match_regardless_of_case() {
+        let table = table(
+            "Location,Site\n\
+             Main OR,Main\n\
+             east-*,East\n\
+             /^west-[0-9]+$/,West\n\
+             ?R,Other\n",
+        );
+        let site = |location: &str| {
+            table
+                .lookup(location)
+                .map(|rule| rule.attributes[0].1.as_str())
+        };
+
+        assert_eq!(site(" main or "), Some("Main"));
+        assert_eq!(site("EAST-3"), Some("East"));
+        assert_eq!(site("West-12"), Some("West"));
+        assert_eq!(site("west-12a"), None);
+        assert_eq!(site("or"), Some("Other"));
+        assert_eq!(site("north"), None);
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let table = table("Location,Site\nOR *,Specific\n*,Any\n");
+        assert_eq!(table.lookup("OR 1").unwrap().attributes[0].1, "Specific");
+        assert_eq!(table.lookup("PACU").unwrap().attributes[0].1, "Any");
+        assert!(
+            LookupTable::from_csv_reader(LookupField::Location, "Location\nOR\n".as_bytes())
+                .is_err()
+        );
+        assert!(LookupTable::from_csv_reader(
+            LookupField::Location,
+            "Location,Site\n/(/,Bad\n".as_bytes()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn apply_lookup_reports_unmatched_and_overwritten() {
+        let mut adverse_events = test_data::adverse_events(vec![
+            TestRecord {
+                location: "OR",
+                extra: vec![("Site", "Old")],
+                ..Default::default()
+            },
+            TestRecord {
+                location: "PACU",
+                extra: vec![("Site", "Old")],
+                ..Default::default()
+            },
+            TestRecord {
+                location: "PACU",
+                extra: vec![("Site", "")],
+                ..Default::default()
+            },
+        ]);
+
+        let report = adverse_events.apply_lookup(&table("Location,Site\nor,Main\n"));
+        assert_eq!(report.matched, 1);
+        assert_eq!(report.unmatched.len(), 1);
+        assert_eq!(
+            (
+                report.unmatched[0].value.as_str(),
+                report.unmatched[0].count
+            ),
+            ("PACU", 2)
+        );
+        assert_eq!(report.overwritten, ["Site"]);
+        assert_eq!(
+            adverse_events.records[0].attributes.get("Site"),
+            Some(&AttributeValue::Text("Main".to_string()))
+        );
+    }
+}
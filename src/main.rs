@@ -1,55 +1,973 @@
-use clap::{App, Arg, SubCommand};
-use csv;
+use chrono::NaiveDate;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
-use adverse_events::{sort_map, AdverseEvents, AdverseEventsView};
+use adverse_events::{
+    sort_map, suppress_labeled_counts, suppress_rates, AdverseEvents, AdverseEventsView,
+    BreakdownType, DeidentifyConfig, Denominated, DenominatorFilter, Denominators, Dimension,
+    Error, FreeTextAction, LookupField, LookupTable, Outcome, Output, OutputFormat, Period,
+    RecordField, SupplementColumn, SupplementConfig, SuppressedRate, SuppressedStratum,
+    SuppressedTimeseries, Suppressible, SuppressionRule, Table, TimeseriesType, VisibilityProfile,
+};
 
-use std::{ffi::OsStr, fs::File, io, path::Path};
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{self, Read, Write},
+    str::FromStr,
+};
 
-fn main() {
+fn main() -> Result<(), Error> {
     let matches = App::new("Adverse events analyzer")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
         .arg(
             Arg::with_name("input")
-                .help("Input record (either CSV, or ZIP archive with a single CSV inside)")
+                .help("Input records (CSV, TSV, XLSX, ZIP, gzip, zstd or encrypted), or - for stdin")
                 .required(true),
         )
+        .arg(
+            Arg::with_name("password-stdin")
+                .long("password-stdin")
+                .help("Read the password for an encrypted input from the first line of stdin"),
+        )
+        .arg(
+            Arg::with_name("lookup")
+                .long("lookup")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Reference CSV of grouping attributes, as <procedure|diagnosis|location>=<path>"),
+        )
+        .arg(
+            Arg::with_name("join")
+                .long("join")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("join-columns")
+                .help("Supplementary CSV keyed on Episode ID"),
+        )
+        .arg(
+            Arg::with_name("join-columns")
+                .long("join-columns")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Columns to take from the matching --join, as <column>=<text|number|bool|date>[,...]"),
+        )
+        .arg(
+            Arg::with_name("suppress-below")
+                .long("suppress-below")
+                .takes_value(true)
+                .help("Suppress counts below this (e.g. 11), with complementary suppression"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .possible_values(&["aggregateOnly", "qiReviewer", "fullPhi"])
+                .default_value("fullPhi")
+                .help("Which record fields may be output"),
+        )
+        .arg(
+            Arg::with_name("visible-attribute")
+                .long("visible-attribute")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Attribute narrower profiles may see and filter on; others are hidden"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["csv", "tsv", "json", "ndjson", "markdown", "table"])
+                .default_value("csv")
+                .help("Output format; json and ndjson match what the web app receives"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .help("Write output to this file instead of stdout"),
+        )
+        .arg(
+            Arg::with_name("denominators")
+                .long("denominators")
+                .takes_value(true)
+                .help("CSV of true case volumes for percentages and rates; only combines with date, location and anesthesiologist filters"),
+        )
+        .arg(
+            Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .help("Only cases on or after this date (YYYY-MM-DD)"),
+        )
+        .arg(
+            Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .help("Only cases on or before this date (YYYY-MM-DD)"),
+        )
+        .arg(
+            Arg::with_name("event")
+                .long("event")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only cases with this adverse event; repeat to require several"),
+        )
+        .arg(
+            Arg::with_name("any-event")
+                .long("any-event")
+                .help("Only cases with at least one adverse event"),
+        )
+        .arg(
+            Arg::with_name("complications")
+                .long("complications")
+                .takes_value(true)
+                .possible_values(&["specified", "occurred"])
+                .help("Only cases where complications were specified, or occurred"),
+        )
+        .arg(
+            Arg::with_name("anesthesiologist")
+                .long("anesthesiologist")
+                .takes_value(true)
+                .help("Only cases with this attending anesthesiologist"),
+        )
+        .arg(
+            Arg::with_name("staff")
+                .long("staff")
+                .takes_value(true)
+                .help("Only cases this person was on the anesthesia staff for"),
+        )
+        .arg(
+            Arg::with_name("location")
+                .long("location")
+                .takes_value(true)
+                .help("Only cases at this location"),
+        )
+        .arg(
+            Arg::with_name("procedure")
+                .long("procedure")
+                .takes_value(true)
+                .help("Only cases with this procedure"),
+        )
+        .arg(
+            Arg::with_name("attribute")
+                .long("attribute")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only cases with an attribute value, as <name>=<value>"),
+        )
+        .arg(
+            Arg::with_name("prior-event")
+                .long("prior-event")
+                .help("Only cases for patients with an earlier adverse event"),
+        )
+        .arg(
+            Arg::with_name("prior-event-within")
+                .long("prior-event-within")
+                .takes_value(true)
+                .help("Only cases within this many days of the patient's earlier adverse event"),
+        )
         .subcommand(SubCommand::with_name("counts"))
+        .subcommand(
+            SubCommand::with_name("deidentify")
+                .about("Writes a de-identified copy of the records")
+                .arg(
+                    Arg::with_name("key-file")
+                        .long("key-file")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Secret key for pseudonyms and date shifts; at least 32 bytes, reused for stable pseudonyms"),
+                )
+                .arg(
+                    Arg::with_name("max-date-shift")
+                        .long("max-date-shift")
+                        .takes_value(true)
+                        .default_value("180")
+                        .help("Largest per-patient date shift in days, 0 to keep dates"),
+                )
+                .arg(
+                    Arg::with_name("free-text")
+                        .long("free-text")
+                        .takes_value(true)
+                        .possible_values(&["keep", "drop", "scrub"])
+                        .default_value("scrub")
+                        .help("What to do with diagnosis, procedure and other text"),
+                )
+                .arg(
+                    Arg::with_name("keep-attribute")
+                        .long("keep-attribute")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Attribute to keep; all others are removed"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("timeseries")
+                .arg(
+                    Arg::with_name("type")
+                        .long("type")
+                        .takes_value(true)
+                        .default_value("event")
+                        .help("event, eventPercentage, complicationSpecified[Percentage], complicationOccurred[Percentage], anesthesiaHours or eventsPer100Hours"),
+                )
+                .arg(
+                    Arg::with_name("period")
+                        .long("period")
+                        .takes_value(true)
+                        .possible_values(&["day", "week", "month", "year"])
+                        .default_value("month"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("breakdown")
+                .arg(
+                    Arg::with_name("type")
+                        .long("type")
+                        .takes_value(true)
+                        .required(true)
+                        .possible_values(&["complications", "event", "age", "bmi", "smoker"]),
+                )
+                .arg(
+                    Arg::with_name("by")
+                        .long("by")
+                        .takes_value(true)
+                        .help("Dimension to stratify the breakdown by"),
+                )
+                .arg(
+                    Arg::with_name("outcome")
+                        .long("outcome")
+                        .takes_value(true)
+                        .help("Report rates of this outcome instead of counts"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("records")
+                .about("Lists records with the fields the profile can view")
+                .arg(
+                    Arg::with_name("offset")
+                        .long("offset")
+                        .takes_value(true)
+                        .default_value("0"),
+                )
+                .arg(Arg::with_name("limit").long("limit").takes_value(true)),
+        )
+        .subcommand(SubCommand::with_name("summary"))
+        .subcommand(SubCommand::with_name("range").about("First and last case dates"))
+        .subcommand(
+            SubCommand::with_name("funnel")
+                .arg(
+                    Arg::with_name("by")
+                        .long("by")
+                        .takes_value(true)
+                        .default_value("anesthesiologist")
+                        .help("Dimension to compare (anesthesiologist, location, procedure, ...)"),
+                )
+                .arg(
+                    Arg::with_name("outcome")
+                        .long("outcome")
+                        .takes_value(true)
+                        .default_value("event")
+                        .help("Outcome to compare (event, complication, or event:<name>)"),
+                ),
+        )
         .get_matches();
 
-    let path = Path::new(matches.value_of("input").unwrap());
-    let file = File::open(path).unwrap();
+    let input = matches.value_of("input").unwrap();
+    if input == "-" && matches.is_present("password-stdin") {
+        return Err(Error::InvalidConfig(
+            "--password-stdin can't be used when reading the input from stdin",
+        ));
+    }
+
+    let password = if matches.is_present("password-stdin") {
+        let mut password = String::new();
+        io::stdin().read_line(&mut password)?;
+        Some(password.trim_end_matches(&['\r', '\n'][..]).to_string())
+    } else {
+        None
+    };
+
+    let data = if input == "-" {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        data
+    } else {
+        std::fs::read(input)?
+    };
+
+    let mut adverse_events = AdverseEvents::load(&data, password.as_deref())?;
 
-    let adverse_events = match path.extension().and_then(OsStr::to_str) {
-        Some("zip") => AdverseEvents::from_zip(file),
-        Some("csv") => AdverseEvents::from_csv_reader(file),
-        Some(ext) => panic!("unsupported extension: {}", ext),
-        None => panic!("input path missing extension"),
+    for lookup in matches.values_of("lookup").into_iter().flatten() {
+        let (field, lookup_path) = lookup.split_once('=').ok_or_else(|| Error::ParseError {
+            type_name: "Lookup",
+            received: lookup.to_string(),
+        })?;
+        let field = LookupField::from_str(field)?;
+        let table = LookupTable::from_csv_reader(field, File::open(lookup_path)?)?;
+
+        let report = adverse_events.apply_lookup(&table);
+        for unmatched in report.unmatched {
+            eprintln!(
+                "{}: no lookup match for {:?} ({} records)",
+                lookup_path, unmatched.value, unmatched.count
+            );
+        }
+        for name in report.overwritten {
+            eprintln!(
+                "{}: replaced existing values of the {} attribute",
+                lookup_path, name
+            );
+        }
     }
-    .unwrap();
 
-    let view: AdverseEventsView = adverse_events.view();
+    let joins: Vec<&str> = matches.values_of("join").into_iter().flatten().collect();
+    let join_columns: Vec<&str> = matches
+        .values_of("join-columns")
+        .into_iter()
+        .flatten()
+        .collect();
+    if joins.len() != join_columns.len() {
+        return Err(Error::InvalidConfig(
+            "each --join needs its own --join-columns",
+        ));
+    }
+    for (join_path, columns) in joins.into_iter().zip(join_columns) {
+        let columns = columns
+            .split(',')
+            .map(SupplementColumn::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let report = adverse_events
+            .join_supplement(&SupplementConfig::new(columns), File::open(join_path)?)?;
+        eprintln!(
+            "{}: joined {} records, {} records without a row, {} rows without a record",
+            join_path,
+            report.matched,
+            report.unmatched_records.len(),
+            report.unmatched_keys.len()
+        );
+        for key in report.duplicate_keys {
+            eprintln!("{}: duplicate Episode ID {}", join_path, key);
+        }
+        for invalid in report.invalid_values {
+            eprintln!(
+                "{}: invalid {} for Episode ID {}: {:?}",
+                join_path, invalid.column, invalid.episode_id, invalid.value
+            );
+        }
+    }
+
+    if let Some(denominators_path) = matches.value_of("denominators") {
+        adverse_events.set_denominators(Denominators::from_csv_reader(File::open(
+            denominators_path,
+        )?)?);
+    }
+
+    // Only created once the input has loaded, so a failed load doesn't
+    // leave an empty file, and --output can name the input
+    let format = OutputFormat::from_str(matches.value_of("format").unwrap())?;
+    let writer: Box<dyn Write> = match matches.value_of("output") {
+        Some(output_path) => Box::new(File::create(output_path)?),
+        None => Box::new(io::stdout()),
+    };
+    let mut output = Output::new(format, writer);
+
+    let profile = VisibilityProfile::from_str(matches.value_of("profile").unwrap())?;
+    let allowed_attributes: BTreeSet<String> = matches
+        .values_of("visible-attribute")
+        .into_iter()
+        .flatten()
+        .map(str::to_string)
+        .collect();
+    let view: AdverseEventsView = filter_view(
+        adverse_events.view(),
+        &matches,
+        profile,
+        &allowed_attributes,
+    )?;
+    // Only worked out for the reports that use them, since they refuse
+    // views with other filters
+    let denominators = || adverse_events.view_denominators(&view, &denominator_filter(&matches)?);
+    let suppression = number_arg(&matches, "suppress-below")?.map(SuppressionRule::new);
 
     match matches.subcommand() {
-        ("counts", _) => {
-            event_counts(&view).unwrap();
+        ("counts", _) => event_counts(&mut output, &view, suppression.as_ref()),
+        ("deidentify", Some(args)) => {
+            deidentify(&mut output, &view, args, profile, &allowed_attributes)
+        }
+        ("timeseries", Some(args)) => {
+            let timeseries_type = TimeseriesType::from_str(args.value_of("type").unwrap())?;
+            let period = Period::from_str(args.value_of("period").unwrap())?;
+            timeseries(
+                &mut output,
+                &view,
+                timeseries_type,
+                period,
+                denominators()?.as_ref(),
+                suppression.as_ref(),
+            )
+        }
+        ("breakdown", Some(args)) => {
+            let breakdown_type = BreakdownType::from_str(args.value_of("type").unwrap())?;
+            let stratify_by = args.value_of("by").map(Dimension::from_str).transpose()?;
+            if let Some(stratify_by) = &stratify_by {
+                profile.check_dimension(stratify_by, &allowed_attributes)?;
+            }
+            let outcome = args
+                .value_of("outcome")
+                .map(Outcome::from_str)
+                .transpose()?;
+            if let Some(outcome) = &outcome {
+                profile.check_outcome(outcome, &allowed_attributes)?;
+            }
+            match outcome {
+                Some(outcome) => breakdown_rates(
+                    &mut output,
+                    &view,
+                    breakdown_type,
+                    stratify_by.as_ref(),
+                    &outcome,
+                    denominators()?.as_ref(),
+                    suppression.as_ref(),
+                ),
+                None => breakdown(
+                    &mut output,
+                    &view,
+                    breakdown_type,
+                    stratify_by.as_ref(),
+                    suppression.as_ref(),
+                ),
+            }
+        }
+        ("records", Some(args)) => {
+            let offset: usize = number_arg(args, "offset")?.unwrap();
+            let limit: Option<usize> = number_arg(args, "limit")?;
+            let page = AdverseEventsView {
+                records: view
+                    .records
+                    .iter()
+                    .copied()
+                    .skip(offset)
+                    .take(limit.unwrap_or(usize::MAX))
+                    .collect(),
+            };
+            records(&mut output, &page, profile, &allowed_attributes)
+        }
+        ("summary", _) => summary(&mut output, &view, suppression.as_ref()),
+        ("range", _) => {
+            let range: Vec<String> = view
+                .date_range()
+                .map(|(from, to)| vec![from.to_string(), to.to_string()])
+                .unwrap_or_default();
+            let mut table = Table::new(["From", "To"]);
+            if !range.is_empty() {
+                table.push(&range);
+            }
+            output.write(&range, &table)
+        }
+        ("funnel", Some(args)) => {
+            let dimension = Dimension::from_str(args.value_of("by").unwrap())?;
+            profile.check_dimension(&dimension, &allowed_attributes)?;
+            let outcome = Outcome::from_str(args.value_of("outcome").unwrap())?;
+            profile.check_outcome(&outcome, &allowed_attributes)?;
+            funnel(&mut output, &view, &dimension, outcome)
+        }
+        (command, _) => unreachable!("unknown subcommand {}", command),
+    }
+}
+
+/// Narrows the view by the global filter flags, refusing filters on fields
+/// the profile can't query.
+fn filter_view<'a>(
+    view: AdverseEventsView<'a>,
+    matches: &ArgMatches<'_>,
+    profile: VisibilityProfile,
+    allowed_attributes: &BTreeSet<String>,
+) -> Result<AdverseEventsView<'a>, Error> {
+    let mut view = view;
+
+    let (from, to) = (date_arg(matches, "from")?, date_arg(matches, "to")?);
+    if from.is_some() || to.is_some() {
+        profile.check_query(RecordField::Date)?;
+        if let Some((first, last)) = view.date_range() {
+            view = view.between(from.unwrap_or(first), to.unwrap_or(last));
         }
-        (command, _) => {
-            panic!("unknown subcommand {}", command);
+    }
+
+    for event in matches.values_of("event").into_iter().flatten() {
+        profile.check_query(RecordField::AdverseEvents)?;
+        view = view.with_event(event);
+    }
+    if matches.is_present("any-event") {
+        profile.check_query(RecordField::AdverseEvents)?;
+        view = view.with_any_event();
+    }
+
+    match matches.value_of("complications") {
+        Some("specified") => {
+            profile.check_query(RecordField::Complications)?;
+            view = view.with_filter(|record| record.complications.is_some());
         }
+        Some("occurred") => {
+            profile.check_query(RecordField::Complications)?;
+            view = view.with_filter(|record| record.complications == Some(true));
+        }
+        _ => {}
+    }
+
+    if let Some(anesthesiologist) = matches.value_of("anesthesiologist") {
+        profile.check_query(RecordField::Anesthesiologist)?;
+        view = view.by_anesthesiologist(anesthesiologist);
+    }
+    if let Some(staff) = matches.value_of("staff") {
+        profile.check_query(RecordField::AnesthesiaStaff)?;
+        view = view.with_staff(staff);
+    }
+    if let Some(location) = matches.value_of("location") {
+        profile.check_query(RecordField::Location)?;
+        view = view.with_filter(|record| record.location == location);
     }
+    if let Some(procedure) = matches.value_of("procedure") {
+        profile.check_query(RecordField::Procedure)?;
+        view = view.with_procedure(procedure);
+    }
+
+    for attribute in matches.values_of("attribute").into_iter().flatten() {
+        let (name, value) = attribute.split_once('=').ok_or_else(|| Error::ParseError {
+            type_name: "AttributeFilter",
+            received: attribute.to_string(),
+        })?;
+        profile.check_attribute(name, allowed_attributes)?;
+        view = view.with_attribute(name, value);
+    }
+
+    if let Some(within_days) = matches.value_of("prior-event-within") {
+        let within_days = within_days.parse().map_err(|_| Error::ParseError {
+            type_name: "Days",
+            received: within_days.to_string(),
+        })?;
+        // Earlier events are found by linking cases on MRN
+        profile.check_query(RecordField::Mrn)?;
+        view = view.with_prior_event(Some(within_days));
+    } else if matches.is_present("prior-event") {
+        profile.check_query(RecordField::Mrn)?;
+        view = view.with_prior_event(None);
+    }
+
+    Ok(view)
+}
+
+fn date_arg(matches: &ArgMatches<'_>, arg: &str) -> Result<Option<NaiveDate>, Error> {
+    matches
+        .value_of(arg)
+        .map(|date| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| Error::ParseError {
+                type_name: "NaiveDate",
+                received: date.to_string(),
+            })
+        })
+        .transpose()
+}
+
+fn number_arg<T: FromStr>(matches: &ArgMatches<'_>, arg: &str) -> Result<Option<T>, Error> {
+    matches
+        .value_of(arg)
+        .map(|number| {
+            number.parse().map_err(|_| Error::ParseError {
+                type_name: "Number",
+                received: number.to_string(),
+            })
+        })
+        .transpose()
+}
+
+/// The global filter flags denominators can follow.
+fn denominator_filter(matches: &ArgMatches<'_>) -> Result<DenominatorFilter, Error> {
+    Ok(DenominatorFilter {
+        from: date_arg(matches, "from")?,
+        to: date_arg(matches, "to")?,
+        location: matches.value_of("location").map(str::to_string),
+        anesthesiologist: matches.value_of("anesthesiologist").map(str::to_string),
+    })
 }
 
-fn event_counts(view: &AdverseEventsView<'_>) -> Result<(), csv::Error> {
-    let mut writer = csv::Writer::from_writer(io::stdout());
+fn event_counts(
+    output: &mut Output<impl Write>,
+    view: &AdverseEventsView<'_>,
+    suppression: Option<&SuppressionRule>,
+) -> Result<(), Error> {
+    let mut table = Table::new(["Adverse event", "Count"]);
+
+    let (result, total) = match suppression {
+        Some(rule) => {
+            let (counts, total) = view.suppressed_event_counts(rule);
+            for (event, count) in &counts {
+                table.push([event.to_string(), count.to_string()]);
+            }
+            (serde_json::to_value(&counts), total.to_string())
+        }
+        None => {
+            let counts = sort_map(view.event_counts());
+            for (event, count) in &counts {
+                table.push([event.to_string(), count.to_string()]);
+            }
+            let total: u64 = counts.iter().map(|(_, count)| *count as u64).sum();
+            (serde_json::to_value(&counts), total.to_string())
+        }
+    };
+    // The total is only for reading; scripts can sum the counts
+    table.push(["Total".to_string(), total]);
+
+    output.write(&result.map_err(io::Error::from)?, &table)
+}
 
-    writer.write_record(&["Adverse event", "Count"])?;
+fn timeseries(
+    output: &mut Output<impl Write>,
+    view: &AdverseEventsView<'_>,
+    timeseries_type: TimeseriesType,
+    period: Period,
+    denominators: Option<&Denominators>,
+    suppression: Option<&SuppressionRule>,
+) -> Result<(), Error> {
+    let denominated = view.denominated_timeseries(timeseries_type, period, denominators);
 
-    let mut total: u64 = 0;
-    for (event, count) in sort_map(view.event_counts()) {
-        total += count as u64;
-        writer.write_record(&[event, &count.to_string()])?;
+    // Shaped like get_timeseries, or get_denominated_timeseries when there
+    // are denominators
+    let (timeseries, result) = match suppression {
+        Some(rule) => {
+            let timeseries =
+                view.suppress_timeseries(denominated.value, timeseries_type, period, rule);
+            let result = match denominators {
+                Some(_) => serde_json::to_value(&Denominated {
+                    denominator: denominated.denominator,
+                    value: &timeseries,
+                }),
+                None => serde_json::to_value(&timeseries),
+            };
+            (timeseries, result)
+        }
+        None => {
+            let result = match denominators {
+                Some(_) => serde_json::to_value(&denominated),
+                None => serde_json::to_value(&denominated.value),
+            };
+            (SuppressedTimeseries::from(denominated.value), result)
+        }
+    };
+
+    let mut table;
+    match timeseries {
+        SuppressedTimeseries::Counts(counts) => {
+            table = Table::new(["Start", "End", "Count"]);
+            for dpc in counts {
+                table.push([
+                    dpc.start.to_string(),
+                    dpc.end.to_string(),
+                    dpc.value.to_string(),
+                ]);
+            }
+        }
+        SuppressedTimeseries::Rates(rates) => {
+            table = Table::new(["Start", "End", "Value"]);
+            for dpr in rates {
+                table.push([
+                    dpr.start.to_string(),
+                    dpr.end.to_string(),
+                    dpr.value.to_string(),
+                ]);
+            }
+        }
+        SuppressedTimeseries::Hours(hours) => {
+            table = Table::new(["Start", "End", "Hours"]);
+            for dph in hours {
+                table.push([
+                    dph.start.to_string(),
+                    dph.end.to_string(),
+                    dph.value.to_string(),
+                ]);
+            }
+        }
     }
 
-    writer.write_record(&["Total", &total.to_string()])?;
+    output.write(&result.map_err(io::Error::from)?, &table)
+}
+
+fn breakdown(
+    output: &mut Output<impl Write>,
+    view: &AdverseEventsView<'_>,
+    breakdown_type: BreakdownType,
+    stratify_by: Option<&Dimension>,
+    suppression: Option<&SuppressionRule>,
+) -> Result<(), Error> {
+    // Shaped like get_breakdown
+    match stratify_by {
+        Some(stratify_by) => {
+            let strata = match suppression {
+                Some(rule) => {
+                    view.suppressed_stratified_breakdown(breakdown_type, Some(stratify_by), rule)
+                }
+                None => view
+                    .get_breakdown(breakdown_type, Some(stratify_by))
+                    .into_iter()
+                    .map(|stratum| SuppressedStratum {
+                        label: stratum.label,
+                        total: Suppressible::Shown(stratum.total),
+                        breakdown: suppress_labeled_counts(
+                            stratum.breakdown,
+                            &SuppressionRule::new(0),
+                        ),
+                    })
+                    .collect(),
+            };
+
+            let mut table = Table::new(["Stratum", "Stratum total", "Group", "Count"]);
+            for stratum in &strata {
+                for cell in &stratum.breakdown {
+                    table.push([
+                        stratum.label.clone(),
+                        stratum.total.to_string(),
+                        cell.label.clone(),
+                        cell.value.to_string(),
+                    ]);
+                }
+            }
+
+            output.write(&strata, &table)
+        }
+        None => {
+            // A zero threshold withholds nothing
+            let cells = view.suppressed_breakdown(
+                breakdown_type,
+                &suppression.copied().unwrap_or(SuppressionRule::new(0)),
+            );
+
+            let mut table = Table::new(["Group", "Count"]);
+            for cell in &cells {
+                table.push([cell.label.clone(), cell.value.to_string()]);
+            }
+
+            output.write(&cells, &table)
+        }
+    }
+}
+
+fn rate_row(rate: &SuppressedRate) -> Vec<String> {
+    vec![
+        rate.label.clone(),
+        rate.total.to_string(),
+        rate.with_outcome.to_string(),
+        rate.rate.to_string(),
+        rate.ci.map(|ci| ci.lower).to_string(),
+        rate.ci.map(|ci| ci.upper).to_string(),
+    ]
+}
+
+const RATE_HEADERS: [&str; 6] = [
+    "Group",
+    "Cases",
+    "With outcome",
+    "Rate",
+    "Lower 95%",
+    "Upper 95%",
+];
+
+fn breakdown_rates(
+    output: &mut Output<impl Write>,
+    view: &AdverseEventsView<'_>,
+    breakdown_type: BreakdownType,
+    stratify_by: Option<&Dimension>,
+    outcome: &Outcome,
+    denominators: Option<&Denominators>,
+    suppression: Option<&SuppressionRule>,
+) -> Result<(), Error> {
+    // A zero threshold withholds nothing, and shown values serialize the
+    // same as unsuppressed ones
+    let rule = suppression.copied().unwrap_or(SuppressionRule::new(0));
+
+    // Shaped like get_breakdown_rates, or get_denominated_breakdown_rates
+    // when there are denominators
+    match (stratify_by, denominators) {
+        (Some(stratify_by), _) => {
+            if denominators.is_some() {
+                eprintln!("Denominators can't be stratified; using the records themselves");
+            }
+            let strata = view.suppressed_stratified_breakdown_rates(
+                breakdown_type,
+                Some(stratify_by),
+                outcome,
+                &rule,
+            );
+
+            let mut table =
+                Table::new(std::iter::once("Stratum").chain(RATE_HEADERS.iter().copied()));
+            for stratum in &strata {
+                for rate in &stratum.breakdown {
+                    table.push(std::iter::once(stratum.label.clone()).chain(rate_row(rate)));
+                }
+            }
+
+            output.write(&strata, &table)
+        }
+        (None, Some(denominators)) => {
+            let denominated =
+                view.denominated_breakdown_rates(breakdown_type, outcome, Some(denominators))?;
+            let denominated = Denominated {
+                denominator: denominated.denominator,
+                value: suppress_rates(denominated.value, &rule),
+            };
+
+            let mut table = Table::new(RATE_HEADERS);
+            for rate in &denominated.value {
+                table.push(rate_row(rate));
+            }
+
+            output.write(&denominated, &table)
+        }
+        (None, None) => {
+            let rates = suppress_rates(view.get_breakdown_rates(breakdown_type, outcome), &rule);
+
+            let mut table = Table::new(RATE_HEADERS);
+            for rate in &rates {
+                table.push(rate_row(rate));
+            }
+
+            output.write(&rates, &table)
+        }
+    }
+}
+
+fn records(
+    output: &mut Output<impl Write>,
+    view: &AdverseEventsView<'_>,
+    profile: VisibilityProfile,
+    allowed_attributes: &BTreeSet<String>,
+) -> Result<(), Error> {
+    // Shaped like get_records
+    output.write(
+        &view.redacted_records(profile, allowed_attributes)?,
+        &view.redacted_table(profile, allowed_attributes)?,
+    )
+}
+
+/// Writes a de-identified copy of the records in the view.
+fn deidentify(
+    output: &mut Output<impl Write>,
+    view: &AdverseEventsView<'_>,
+    args: &ArgMatches<'_>,
+    profile: VisibilityProfile,
+    allowed_attributes: &BTreeSet<String>,
+) -> Result<(), Error> {
+    let key = std::fs::read(args.value_of("key-file").unwrap())?;
+    let mut config = DeidentifyConfig::new(key)?;
+    config.max_date_shift_days = number_arg(args, "max-date-shift")?.unwrap();
+    config.free_text = FreeTextAction::from_str(args.value_of("free-text").unwrap())?;
+    config.kept_attributes = args
+        .values_of("keep-attribute")
+        .into_iter()
+        .flatten()
+        .map(str::to_string)
+        .collect();
+
+    // A copy, since records are de-identified in place
+    let mut deidentified = AdverseEvents {
+        records: view
+            .records
+            .iter()
+            .map(|record| (*record).clone())
+            .collect(),
+        denominators: None,
+    };
+    let report = deidentified.deidentify(&config)?;
+    records(output, &deidentified.view(), profile, allowed_attributes)?;
+
+    eprintln!(
+        "De-identified {} records for {} patients",
+        report.records, report.patients
+    );
+    for transformation in report.transformations {
+        eprintln!(
+            "{}: {} ({} records changed)",
+            transformation.field, transformation.transformation, transformation.records_changed
+        );
+    }
 
     Ok(())
 }
+
+fn summary(
+    output: &mut Output<impl Write>,
+    view: &AdverseEventsView<'_>,
+    suppression: Option<&SuppressionRule>,
+) -> Result<(), Error> {
+    // Each figure stands alone, so only small counts themselves are
+    // withheld, and a zero threshold withholds nothing
+    let summary = view
+        .summary()
+        .suppressed(&suppression.copied().unwrap_or(SuppressionRule::new(0)));
+
+    let date = |date: Option<NaiveDate>| date.map(|date| date.to_string()).unwrap_or_default();
+    let mut table = Table::new(["Measure", "Value"]);
+    table.push(["First date".to_string(), date(summary.first_date)]);
+    table.push(["Last date".to_string(), date(summary.last_date)]);
+    for (measure, value) in [
+        ("Records", &summary.records),
+        ("Patients", &summary.patients),
+        ("With adverse event", &summary.with_event),
+        ("Adverse events", &summary.events),
+        ("Complications specified", &summary.complications_specified),
+        ("Complications occurred", &summary.complications_occurred),
+        ("Anesthesiologists", &summary.anesthesiologists),
+        ("Locations", &summary.locations),
+    ] {
+        table.push([measure.to_string(), value.to_string()]);
+    }
+    table.push([
+        "Anesthesia hours".to_string(),
+        summary.anesthesia_hours.to_string(),
+    ]);
+
+    // Shaped like get_summary
+    output.write(&summary, &table)
+}
+
+fn funnel(
+    output: &mut Output<impl Write>,
+    view: &AdverseEventsView<'_>,
+    dimension: &Dimension,
+    outcome: Outcome,
+) -> Result<(), Error> {
+    let funnel = view.funnel(dimension, outcome);
+
+    let mut table = Table::new([
+        "Group",
+        "Cases",
+        "Events",
+        "Rate",
+        "Lower 95%",
+        "Upper 95%",
+        "Lower 99.8%",
+        "Upper 99.8%",
+        "Flag",
+    ]);
+    for point in &funnel.points {
+        table.push([
+            point.label.clone(),
+            point.cases.to_string(),
+            point.events.to_string(),
+            point.rate.to_string(),
+            point.limits.warning.lower.to_string(),
+            point.limits.warning.upper.to_string(),
+            point.limits.control.lower.to_string(),
+            point.limits.control.upper.to_string(),
+            point.flag.to_string(),
+        ]);
+    }
+
+    // Shaped like get_funnel
+    output.write(&funnel, &table)
+}
@@ -0,0 +1,68 @@
+use super::*;
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Outcome {
+    AnyEvent,
+    Event(String),
+    ComplicationOccurred,
+    // Joined yes/no column, or a count where anything above zero counts
+    Attribute(String),
+}
+
+impl Outcome {
+    pub fn occurred(&self, record: &AdverseEventRecord) -> bool {
+        match self {
+            Outcome::AnyEvent => !record.adverse_events.is_empty(),
+            Outcome::Event(event) => record.adverse_events.iter().any(|e| e == event),
+            Outcome::ComplicationOccurred => record.complications == Some(true),
+            Outcome::Attribute(name) => match record.attributes.get(name) {
+                Some(AttributeValue::Bool(value)) => *value,
+                Some(AttributeValue::Number(value)) => *value > 0.0,
+                _ => false,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::AnyEvent => write!(f, "Any event"),
+            Outcome::Event(event) => write!(f, "{}", event),
+            Outcome::ComplicationOccurred => write!(f, "Complication occurred"),
+            Outcome::Attribute(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl FromStr for Outcome {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "event" | "anyEvent" => Ok(Outcome::AnyEvent),
+            "complication" | "complicationOccurred" => Ok(Outcome::ComplicationOccurred),
+            x => match (x.strip_prefix("event:"), x.strip_prefix("attribute:")) {
+                (Some(event), _) if !event.is_empty() => Ok(Outcome::Event(event.to_string())),
+                (_, Some(name)) if !name.is_empty() => Ok(Outcome::Attribute(name.to_string())),
+                _ => Err(crate::Error::ParseError {
+                    type_name: "Outcome",
+                    received: x.to_string(),
+                }),
+            },
+        }
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn with_outcome(&self, outcome: &Outcome) -> Self {
+        self.with_filter(|record| outcome.occurred(record))
+    }
+
+    pub fn count_outcome(&self, outcome: &Outcome) -> usize {
+        self.count(|record| outcome.occurred(record))
+    }
+}
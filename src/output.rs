@@ -0,0 +1,234 @@
+use super::*;
+
+use std::{io::Write, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Csv,
+    Tsv,
+    Json,
+    // One JSON value per line, each element if the output is a list
+    Ndjson,
+    Markdown,
+    // Aligned plain text for reading in a terminal
+    Table,
+}
+
+impl FromStr for OutputFormat {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "markdown" => Ok(OutputFormat::Markdown),
+            "table" => Ok(OutputFormat::Table),
+            x => Err(crate::Error::ParseError {
+                type_name: "OutputFormat",
+                received: x.to_string(),
+            }),
+        }
+    }
+}
+
+/// Rows of display strings, for the tabular output formats.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new<I, S>(headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        Table {
+            headers: headers
+                .into_iter()
+                .map(|header| header.to_string())
+                .collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push<I, S>(&mut self, row: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.rows
+            .push(row.into_iter().map(|cell| cell.to_string()).collect());
+    }
+
+    fn write_delimited<W>(&self, writer: W, delimiter: u8) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(writer);
+
+        writer.write_record(&self.headers)?;
+        for row in &self.rows {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn write_markdown<W>(&self, mut writer: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let escape = |cell: &String| cell.replace('|', "\\|").replace('\n', "<br>");
+        let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+
+        writeln!(
+            writer,
+            "{}",
+            line(self.headers.iter().map(escape).collect())
+        )?;
+        writeln!(
+            writer,
+            "{}",
+            line(self.headers.iter().map(|_| "---".to_string()).collect())
+        )?;
+        for row in &self.rows {
+            writeln!(writer, "{}", line(row.iter().map(escape).collect()))?;
+        }
+
+        Ok(())
+    }
+
+    fn write_aligned<W>(&self, mut writer: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        let flatten = |cell: &String| cell.replace('\n', ", ");
+        let mut widths: Vec<usize> = self
+            .headers
+            .iter()
+            .map(|header| header.chars().count())
+            .collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(flatten(cell).chars().count());
+            }
+        }
+
+        let mut line = |cells: Vec<String>| {
+            let padded: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = *width))
+                .collect();
+            writeln!(writer, "{}", padded.join("  ").trim_end())
+        };
+
+        line(self.headers.clone())?;
+        line(widths.iter().map(|width| "-".repeat(*width)).collect())?;
+        for row in &self.rows {
+            line(row.iter().map(flatten).collect())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes results in a chosen format: the JSON formats serialize the value
+/// itself, the rest write its table.
+pub struct Output<W: Write> {
+    format: OutputFormat,
+    writer: W,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(format: OutputFormat, writer: W) -> Self {
+        Output { format, writer }
+    }
+
+    pub fn write<T>(&mut self, value: &T, table: &Table) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self.format {
+            OutputFormat::Csv => table.write_delimited(&mut self.writer, b',')?,
+            OutputFormat::Tsv => table.write_delimited(&mut self.writer, b'\t')?,
+            OutputFormat::Json => {
+                serde_json::to_writer(&mut self.writer, value).map_err(io::Error::from)?;
+                writeln!(self.writer)?;
+            }
+            OutputFormat::Ndjson => match serde_json::to_value(value).map_err(io::Error::from)? {
+                serde_json::Value::Array(values) => {
+                    for value in values {
+                        writeln!(self.writer, "{}", value)?;
+                    }
+                }
+                value => writeln!(self.writer, "{}", value)?,
+            },
+            OutputFormat::Markdown => table.write_markdown(&mut self.writer)?,
+            OutputFormat::Table => table.write_aligned(&mut self.writer)?,
+        }
+
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written<T: Serialize + ?Sized>(format: OutputFormat, value: &T) -> String {
+        let mut table = Table::new(vec!["label", "count"]);
+        table.push(vec!["a|b", "1"]);
+        table.push(vec!["long\nname", "10"]);
+
+        let mut buffer = Vec::new();
+        Output::new(format, &mut buffer)
+            .write(value, &table)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn tabular_formats_write_the_table() {
+        let counts = [1, 10];
+
+        assert_eq!(
+            written(OutputFormat::Csv, &counts),
+            "label,count\na|b,1\n\"long\nname\",10\n"
+        );
+        assert_eq!(
+            written(OutputFormat::Tsv, &counts),
+            "label\tcount\na|b\t1\n\"long\nname\"\t10\n"
+        );
+        assert_eq!(
+            written(OutputFormat::Markdown, &counts),
+            "| label | count |\n| --- | --- |\n| a\\|b | 1 |\n| long<br>name | 10 |\n"
+        );
+        assert_eq!(
+            written(OutputFormat::Table, &counts),
+            "label       count\n----------  -----\na|b         1\nlong, name  10\n"
+        );
+    }
+
+    #[test]
+    fn json_formats_write_the_value() {
+        assert_eq!(written(OutputFormat::Json, &[1, 10]), "[1,10]\n");
+        assert_eq!(written(OutputFormat::Ndjson, &[1, 10]), "1\n10\n");
+        assert_eq!(written(OutputFormat::Ndjson, "total"), "\"total\"\n");
+    }
+
+    #[test]
+    fn output_format_from_str() {
+        assert_eq!("ndjson".parse().ok(), Some(OutputFormat::Ndjson));
+        assert_eq!("table".parse().ok(), Some(OutputFormat::Table));
+        assert!("xlsx".parse::<OutputFormat>().is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use super::*;
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RepeatMatch {
+    // The repeat must share at least one adverse event with the earlier episode
+    SameEvent,
+    // Any adverse event after an earlier episode with any adverse event
+    AnyEvent,
+}
+
+impl FromStr for RepeatMatch {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "same" | "sameEvent" => Ok(RepeatMatch::SameEvent),
+            "any" | "anyEvent" => Ok(RepeatMatch::AnyEvent),
+            x => Err(crate::Error::ParseError {
+                type_name: "RepeatMatch",
+                received: x.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct PatientTimeline<'a> {
+    pub mrn: &'a str,
+    // Ordered by anesthesia start
+    pub episodes: Vec<&'a AdverseEventRecord>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct RepeatEvent {
+    pub mrn: String,
+    pub prior_episode_id: String,
+    pub prior_date: NaiveDate,
+    pub episode_id: String,
+    pub date: NaiveDate,
+    pub days_between: i64,
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PatientIndex<'a> {
+    timelines: Vec<PatientTimeline<'a>>,
+}
+
+impl<'a> PatientIndex<'a> {
+    pub fn timelines(&self) -> &[PatientTimeline<'a>] {
+        &self.timelines
+    }
+
+    pub fn get(&self, mrn: &str) -> Option<&PatientTimeline<'a>> {
+        self.timelines
+            .binary_search_by(|timeline| timeline.mrn.cmp(mrn))
+            .ok()
+            .map(|i| &self.timelines[i])
+    }
+
+    pub fn repeat_events(&self, within_days: i64, repeat_match: RepeatMatch) -> Vec<RepeatEvent> {
+        let mut repeats = Vec::new();
+
+        for timeline in &self.timelines {
+            for (i, episode) in timeline.episodes.iter().enumerate() {
+                if episode.adverse_events.is_empty() {
+                    continue;
+                }
+
+                let start = episode.anesthesia_interval().0;
+                let mut earlier = timeline.episodes[..i].iter().filter(|prior| {
+                    !prior.adverse_events.is_empty()
+                        && prior.anesthesia_interval().0 < start
+                        && (episode.date - prior.date).num_days() <= within_days
+                });
+
+                let (prior, events) = match repeat_match {
+                    RepeatMatch::AnyEvent => (earlier.next_back(), episode.adverse_events.clone()),
+                    RepeatMatch::SameEvent => {
+                        let earlier: Vec<_> = earlier
+                            .filter(|prior| {
+                                episode
+                                    .adverse_events
+                                    .iter()
+                                    .any(|event| prior.adverse_events.contains(event))
+                            })
+                            .collect();
+                        let events = episode
+                            .adverse_events
+                            .iter()
+                            .filter(|event| {
+                                earlier
+                                    .iter()
+                                    .any(|prior| prior.adverse_events.contains(event))
+                            })
+                            .cloned()
+                            .collect();
+
+                        (earlier.last().copied(), events)
+                    }
+                };
+
+                if let Some(prior) = prior {
+                    repeats.push(RepeatEvent {
+                        mrn: timeline.mrn.to_string(),
+                        prior_episode_id: prior.episode_id.clone(),
+                        prior_date: prior.date,
+                        episode_id: episode.episode_id.clone(),
+                        date: episode.date,
+                        days_between: (episode.date - prior.date).num_days(),
+                        events,
+                    });
+                }
+            }
+        }
+
+        repeats
+    }
+}
+
+impl AdverseEvents {
+    pub fn patient_index(&self) -> PatientIndex {
+        self.view().patient_index()
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn patient_index(&self) -> PatientIndex<'a> {
+        let mut by_mrn: HashMap<&'a str, Vec<&'a AdverseEventRecord>> = HashMap::new();
+        for record in &self.records {
+            let mrn = record.mrn.trim();
+            if !mrn.is_empty() {
+                by_mrn.entry(mrn).or_default().push(*record);
+            }
+        }
+
+        let timelines = sort_map(by_mrn)
+            .into_iter()
+            .map(|(mrn, mut episodes)| {
+                episodes.sort_by_key(|record| record.anesthesia_interval().0);
+                PatientTimeline { mrn, episodes }
+            })
+            .collect();
+
+        PatientIndex { timelines }
+    }
+
+    /// Episodes whose patient had an adverse event in an earlier episode,
+    /// optionally only within the given number of days.
+    pub fn with_prior_event(&self, within_days: Option<i64>) -> Self {
+        self.with_filter(
+            |record| match (record.days_since_prior_event, within_days) {
+                (Some(days), Some(within_days)) => days <= within_days,
+                (Some(_), None) => true,
+                (None, _) => false,
+            },
+        )
+    }
+}
+
+/// Records, for every episode, how long it has been since the patient's
+/// most recent earlier episode with an adverse event. This looks at the
+/// whole data set so later filtering doesn't hide a patient's history.
+pub(crate) fn compute_prior_events(records: &mut [AdverseEventRecord]) {
+    let mut by_mrn: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, record) in records.iter().enumerate() {
+        let mrn = record.mrn.trim();
+        if !mrn.is_empty() {
+            by_mrn.entry(mrn).or_default().push(i);
+        }
+    }
+    let by_mrn: Vec<Vec<usize>> = by_mrn.into_values().collect();
+
+    for mut indices in by_mrn {
+        indices.sort_by_key(|i| records[*i].anesthesia_interval().0);
+
+        let mut prior: Option<NaiveDate> = None;
+        let mut pending: Vec<usize> = Vec::new();
+        let mut current_start = None;
+
+        // Episodes starting at the same moment aren't prior to one another,
+        // so each batch of simultaneous starts is resolved before any of
+        // them can become the most recent event
+        for i in indices {
+            let start = records[i].anesthesia_interval().0;
+            if current_start != Some(start) {
+                for j in pending.drain(..) {
+                    if !records[j].adverse_events.is_empty() {
+                        prior = Some(records[j].date);
+                    }
+                }
+                current_start = Some(start);
+            }
+
+            records[i].days_since_prior_event =
+                prior.map(|date| (records[i].date - date).num_days());
+            pending.push(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    fn adverse_events() -> AdverseEvents {
+        let episode = |mrn: &str, date, adverse_events| TestRecord {
+            mrn: Some(mrn.to_string()),
+            date,
+            adverse_events,
+            ..Default::default()
+        };
+
+        test_data::adverse_events(vec![
+            episode("P1", "01/01/19", "nausea"),
+            // Two episodes starting together
+            episode("P1", "01/05/19", "nausea"),
+            episode("P1", "01/05/19", "pain"),
+            episode("P1", "01/20/19", ""),
+            episode("P1", "02/15/19", "pain"),
+            episode("P2", "01/01/19", ""),
+            episode("P2", "01/02/19", "nausea"),
+            episode(" ", "01/03/19", "nausea"),
+        ])
+    }
+
+    #[test]
+    fn prior_events_skip_simultaneous_starts() {
+        let adverse_events = adverse_events();
+        let days: Vec<Option<i64>> = adverse_events
+            .records
+            .iter()
+            .map(|record| record.days_since_prior_event)
+            .collect();
+
+        assert_eq!(
+            days,
+            [None, Some(4), Some(4), Some(15), Some(41), None, None, None]
+        );
+        assert_eq!(adverse_events.view().with_prior_event(Some(10)).len(), 2);
+        assert_eq!(adverse_events.view().with_prior_event(None).len(), 4);
+    }
+
+    #[test]
+    fn patient_index_orders_episodes() {
+        let adverse_events = adverse_events();
+        let index = adverse_events.patient_index();
+
+        let mrns: Vec<&str> = index
+            .timelines()
+            .iter()
+            .map(|timeline| timeline.mrn)
+            .collect();
+        assert_eq!(mrns, ["P1", "P2"]);
+        let p1 = index.get("P1").unwrap();
+        assert_eq!(p1.episodes.len(), 5);
+        assert!(p1
+            .episodes
+            .windows(2)
+            .all(|pair| pair[0].date <= pair[1].date));
+        assert!(index.get("P3").is_none());
+    }
+
+    #[test]
+    fn repeat_events_by_match() {
+        let adverse_events = adverse_events();
+        let index = adverse_events.patient_index();
+        let repeats = |within_days, repeat_match| -> Vec<(String, String, Vec<String>)> {
+            index
+                .repeat_events(within_days, repeat_match)
+                .into_iter()
+                .map(|repeat| (repeat.prior_episode_id, repeat.episode_id, repeat.events))
+                .collect()
+        };
+        let repeat = |prior: &str, episode: &str, events: &[&str]| {
+            (
+                prior.to_string(),
+                episode.to_string(),
+                events.iter().map(|event| event.to_string()).collect(),
+            )
+        };
+
+        assert_eq!(
+            repeats(30, RepeatMatch::AnyEvent),
+            [
+                repeat("E0", "E1", &["nausea"]),
+                repeat("E0", "E2", &["pain"])
+            ]
+        );
+        assert_eq!(
+            repeats(60, RepeatMatch::SameEvent),
+            [
+                repeat("E0", "E1", &["nausea"]),
+                repeat("E2", "E4", &["pain"])
+            ]
+        );
+    }
+
+    #[test]
+    fn repeat_match_from_str() {
+        assert!(matches!("same".parse(), Ok(RepeatMatch::SameEvent)));
+        assert!(matches!("anyEvent".parse(), Ok(RepeatMatch::AnyEvent)));
+        assert!("other".parse::<RepeatMatch>().is_err());
+    }
+}
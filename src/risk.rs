@@ -0,0 +1,350 @@
+use super::*;
+
+const RISK_FACTORS: [&str; 5] = ["(Intercept)", "asa", "age", "bmi", "smoker"];
+const MAX_ITERATIONS: usize = 50;
+const CONVERGENCE_TOLERANCE: f64 = 1e-8;
+// Small ridge penalty so a constant factor (e.g. no smokers in the data)
+// doesn't make the information matrix singular
+const RIDGE: f64 = 1e-6;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RiskCoefficient {
+    pub name: String,
+    pub estimate: f64,
+    pub standard_error: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RiskModel {
+    pub outcome: Outcome,
+    pub coefficients: Vec<RiskCoefficient>,
+    pub cases: usize,
+    pub events: usize,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ObservedExpected {
+    pub label: String,
+    pub cases: usize,
+    pub observed: usize,
+    pub expected: f64,
+    // None when no events were expected, where the ratio is undefined
+    pub ratio: Option<f64>,
+    pub ci: Option<ConfidenceInterval>,
+}
+
+impl ObservedExpected {
+    pub fn new(label: String, cases: usize, observed: usize, expected: f64) -> Self {
+        let (ratio, ci) = if expected > 0.0 && expected.is_finite() {
+            let ci = poisson_ci(observed, Z_95);
+            (
+                Some(observed as f64 / expected),
+                Some(ConfidenceInterval {
+                    lower: ci.lower / expected,
+                    upper: ci.upper / expected,
+                }),
+            )
+        } else {
+            (None, None)
+        };
+
+        ObservedExpected {
+            label,
+            cases,
+            observed,
+            expected,
+            ratio,
+            ci,
+        }
+    }
+}
+
+// Records missing a risk factor are left out of both the fit and the
+// observed-expected counts
+fn has_risk_factors(x: &[f64; 5]) -> bool {
+    x.iter().all(|v| v.is_finite())
+}
+
+fn risk_factors(record: &AdverseEventRecord) -> [f64; 5] {
+    [
+        1.0,
+        record.asa as f64,
+        record.age as f64,
+        record.bmi,
+        if record.smoker { 1.0 } else { 0.0 },
+    ]
+}
+
+impl RiskModel {
+    /// Fits a logistic regression of the outcome on ASA, age, BMI and smoking
+    /// status using iteratively reweighted least squares.
+    pub fn fit(view: &AdverseEventsView<'_>, outcome: Outcome) -> Result<Self, Error> {
+        let n = RISK_FACTORS.len();
+        let data: Vec<([f64; 5], f64)> = view
+            .records
+            .iter()
+            .map(|record| {
+                (
+                    risk_factors(record),
+                    if outcome.occurred(record) { 1.0 } else { 0.0 },
+                )
+            })
+            .filter(|(x, _)| has_risk_factors(x))
+            .collect();
+
+        let cases = data.len();
+        let events = data.iter().filter(|(_, y)| *y > 0.0).count();
+
+        if cases == 0 {
+            return Err(Error::InsufficientData("no records to fit risk model"));
+        }
+        if events == 0 || events == cases {
+            return Err(Error::InsufficientData(
+                "outcome must both occur and not occur to fit risk model",
+            ));
+        }
+
+        let mut beta = vec![0.0; n];
+        let mean = events as f64 / cases as f64;
+        beta[0] = (mean / (1.0 - mean)).ln();
+
+        let mut iterations = 0;
+        let mut converged = false;
+
+        while iterations < MAX_ITERATIONS {
+            iterations += 1;
+
+            let (gradient, information) = Self::score(&data, &beta);
+            let inverse = invert_matrix(&information)
+                .ok_or(Error::InsufficientData("risk factors are collinear"))?;
+
+            let mut max_step: f64 = 0.0;
+            for i in 0..n {
+                let step: f64 = (0..n).map(|j| inverse[i][j] * gradient[j]).sum();
+                beta[i] += step;
+                max_step = max_step.max(step.abs());
+            }
+
+            if !beta.iter().all(|b| b.is_finite()) {
+                return Err(Error::InsufficientData("risk model did not converge"));
+            }
+
+            if max_step < CONVERGENCE_TOLERANCE {
+                converged = true;
+                break;
+            }
+        }
+
+        let (_, information) = Self::score(&data, &beta);
+        let covariance = invert_matrix(&information)
+            .ok_or(Error::InsufficientData("risk factors are collinear"))?;
+
+        let coefficients = RISK_FACTORS
+            .iter()
+            .enumerate()
+            .map(|(i, name)| RiskCoefficient {
+                name: name.to_string(),
+                estimate: beta[i],
+                standard_error: covariance[i][i].sqrt(),
+            })
+            .collect();
+
+        Ok(RiskModel {
+            outcome,
+            coefficients,
+            cases,
+            events,
+            iterations,
+            converged,
+        })
+    }
+
+    fn score(data: &[([f64; 5], f64)], beta: &[f64]) -> (Vec<f64>, Vec<Vec<f64>>) {
+        let n = beta.len();
+        let mut gradient = vec![0.0; n];
+        let mut information = vec![vec![0.0; n]; n];
+
+        for (x, y) in data {
+            let p = logistic(x.iter().zip(beta).map(|(x, b)| x * b).sum());
+            let weight = p * (1.0 - p);
+            for i in 0..n {
+                gradient[i] += (y - p) * x[i];
+                for j in 0..n {
+                    information[i][j] += weight * x[i] * x[j];
+                }
+            }
+        }
+
+        for i in 1..n {
+            gradient[i] -= RIDGE * beta[i];
+            information[i][i] += RIDGE;
+        }
+
+        (gradient, information)
+    }
+
+    pub fn expected_probability(&self, record: &AdverseEventRecord) -> f64 {
+        logistic(
+            risk_factors(record)
+                .iter()
+                .zip(&self.coefficients)
+                .map(|(x, coefficient)| x * coefficient.estimate)
+                .sum(),
+        )
+    }
+
+    pub fn observed_expected(
+        &self,
+        label: String,
+        view: &AdverseEventsView<'_>,
+    ) -> ObservedExpected {
+        let records: Vec<&AdverseEventRecord> = view
+            .records
+            .iter()
+            .copied()
+            .filter(|record| has_risk_factors(&risk_factors(record)))
+            .collect();
+        let observed = records
+            .iter()
+            .filter(|record| self.outcome.occurred(record))
+            .count();
+        let expected: f64 = records
+            .iter()
+            .map(|record| self.expected_probability(record))
+            .sum();
+
+        ObservedExpected::new(label, records.len(), observed, expected)
+    }
+
+    pub fn write_coefficients_csv<W>(&self, writer: W) -> Result<(), Error>
+    where
+        W: io::Write,
+    {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["Risk factor", "Estimate", "Standard error"])?;
+        for coefficient in &self.coefficients {
+            writer.write_record(&[
+                coefficient.name.clone(),
+                coefficient.estimate.to_string(),
+                coefficient.standard_error.to_string(),
+            ])?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn observed_expected(
+        &self,
+        model: &RiskModel,
+        dimension: &Dimension,
+    ) -> Vec<ObservedExpected> {
+        self.group_by_dimension(dimension)
+            .into_iter()
+            .map(|group| model.observed_expected(group.label, &group.value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    fn records() -> Vec<TestRecord> {
+        (0..80usize)
+            .map(|i| {
+                let asa = 1 + i % 4;
+                let smoker = i % 3 == 0;
+                // Events get likelier with ASA and smoking, without separating
+                let event = (i * 37) % 10 < asa + if smoker { 2 } else { 0 };
+                TestRecord {
+                    asa: asa as u8,
+                    smoker,
+                    age: (20 + i % 60) as u8,
+                    bmi: 20.0 + ((i * 7) % 15) as f64,
+                    ..TestRecord::with_event(event)
+                }
+            })
+            .collect()
+    }
+
+    fn adverse_events() -> AdverseEvents {
+        test_data::adverse_events(records())
+    }
+
+    #[test]
+    fn fit_converges_and_calibrates_overall() {
+        let adverse_events = adverse_events();
+        let view = adverse_events.view();
+        let model = RiskModel::fit(&view, Outcome::AnyEvent).unwrap();
+
+        assert!(model.converged);
+        assert_eq!(model.cases, 80);
+        // With an intercept the fitted probabilities sum to the observed
+        // count, so the overall O/E ratio is 1
+        let overall = model.observed_expected("All".to_string(), &view);
+        assert_eq!(overall.observed, model.events);
+        assert!((overall.ratio.unwrap() - 1.0).abs() < 1e-6);
+        let asa = &model.coefficients[1];
+        assert!(asa.estimate > 0.0 && asa.standard_error > 0.0);
+    }
+
+    #[test]
+    fn fit_needs_both_outcomes() {
+        let adverse_events = adverse_events();
+        assert!(matches!(
+            RiskModel::fit(&adverse_events.view(), Outcome::Event("none".to_string())),
+            Err(Error::InsufficientData(_))
+        ));
+    }
+
+    #[test]
+    fn observed_expected_skips_records_the_fit_skips() {
+        let mut records = records();
+        records.push(TestRecord {
+            bmi: f64::NAN,
+            ..TestRecord::with_event(true)
+        });
+        let adverse_events = test_data::adverse_events(records);
+        let view = adverse_events.view();
+        let model = RiskModel::fit(&view, Outcome::AnyEvent).unwrap();
+
+        let overall = model.observed_expected("All".to_string(), &view);
+        assert_eq!(model.cases, 80);
+        assert_eq!(overall.cases, 80);
+        assert_eq!(overall.observed, model.events);
+    }
+
+    #[test]
+    fn fit_refuses_diverging_coefficients() {
+        // BMIs too large to square leave the information matrix infinite
+        let adverse_events =
+            test_data::adverse_events(records().into_iter().map(|record| TestRecord {
+                bmi: 1e200,
+                ..record
+            }));
+        assert!(matches!(
+            RiskModel::fit(&adverse_events.view(), Outcome::AnyEvent),
+            Err(Error::InsufficientData(_))
+        ));
+    }
+
+    #[test]
+    fn observed_expected_is_undefined_without_expected_events() {
+        let zero = ObservedExpected::new("Zero".to_string(), 5, 0, 0.0);
+        assert!(zero.ratio.is_none() && zero.ci.is_none());
+
+        let ratio = ObservedExpected::new("Two".to_string(), 50, 4, 2.0);
+        assert_eq!(ratio.ratio, Some(2.0));
+        let ci = ratio.ci.unwrap();
+        assert!(ci.lower < 2.0 && ci.upper > 2.0);
+    }
+}
@@ -0,0 +1,203 @@
+use super::*;
+
+const CREDIBLE_LEVEL: f64 = 0.95;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct BetaPrior {
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl BetaPrior {
+    /// Method of moments fit of a beta-binomial prior to grouped counts,
+    /// given as (cases, events) pairs.
+    pub fn fit(groups: &[(usize, usize)]) -> Option<Self> {
+        let groups: Vec<(f64, f64)> = groups
+            .iter()
+            .filter(|(cases, _)| *cases > 0)
+            .map(|(cases, events)| (*cases as f64, *events as f64))
+            .collect();
+
+        let total_cases: f64 = groups.iter().map(|(cases, _)| cases).sum();
+        let total_events: f64 = groups.iter().map(|(_, events)| events).sum();
+
+        if groups.is_empty() || total_cases == 0.0 {
+            return None;
+        }
+
+        let mean = total_events / total_cases;
+        if mean <= 0.0 || mean >= 1.0 {
+            return None;
+        }
+
+        let between: f64 = groups
+            .iter()
+            .map(|(cases, events)| cases * (events / cases - mean).powi(2))
+            .sum();
+        let squared_cases: f64 = groups.iter().map(|(cases, _)| cases * cases).sum();
+        let denominator = total_cases - squared_cases / total_cases;
+
+        // Intraclass correlation; with no spread beyond binomial noise the
+        // prior is made as strong as the data so every group is pooled
+        let correlation = if denominator > 0.0 {
+            (between / (mean * (1.0 - mean)) - (groups.len() as f64 - 1.0)) / denominator
+        } else {
+            0.0
+        };
+        let strength = if correlation > 0.0 {
+            ((1.0 - correlation) / correlation).min(total_cases)
+        } else {
+            total_cases
+        };
+
+        Some(BetaPrior {
+            alpha: mean * strength,
+            beta: (1.0 - mean) * strength,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ShrunkenRate {
+    pub label: String,
+    pub cases: usize,
+    pub events: usize,
+    pub raw_rate: f64,
+    pub shrunken_rate: f64,
+    pub credible_interval: ConfidenceInterval,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct ShrunkenRates {
+    pub outcome: Outcome,
+    pub prior: Option<BetaPrior>,
+    pub groups: Vec<ShrunkenRate>,
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn shrunken_rates(&self, dimension: &Dimension, outcome: Outcome) -> ShrunkenRates {
+        let groups: Vec<(String, usize, usize)> = self
+            .group_by_dimension(dimension)
+            .into_iter()
+            .filter(|group| !group.value.records.is_empty())
+            .map(|group| {
+                (
+                    group.label,
+                    group.value.len(),
+                    group.value.count_outcome(&outcome),
+                )
+            })
+            .collect();
+
+        let counts: Vec<(usize, usize)> = groups
+            .iter()
+            .map(|(_, cases, events)| (*cases, *events))
+            .collect();
+        let prior = BetaPrior::fit(&counts);
+
+        let tail = (1.0 - CREDIBLE_LEVEL) / 2.0;
+
+        ShrunkenRates {
+            outcome,
+            prior,
+            groups: groups
+                .into_iter()
+                .map(|(label, cases, events)| {
+                    let raw_rate = events as f64 / cases as f64;
+
+                    // Without a prior (outcome never or always occurred)
+                    // there is nothing to shrink towards
+                    let (alpha, beta) = match prior {
+                        Some(prior) => (
+                            prior.alpha + events as f64,
+                            prior.beta + (cases - events) as f64,
+                        ),
+                        None => (events as f64 + 0.5, (cases - events) as f64 + 0.5),
+                    };
+
+                    ShrunkenRate {
+                        label,
+                        cases,
+                        events,
+                        raw_rate,
+                        shrunken_rate: match prior {
+                            Some(_) => alpha / (alpha + beta),
+                            None => raw_rate,
+                        },
+                        credible_interval: ConfidenceInterval {
+                            lower: beta_quantile(tail, alpha, beta),
+                            upper: beta_quantile(1.0 - tail, alpha, beta),
+                        },
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    // (location, cases, events)
+    fn adverse_events(groups: &[(&'static str, usize, usize)]) -> AdverseEvents {
+        test_data::adverse_events(groups.iter().flat_map(|&(location, cases, events)| {
+            (0..cases).map(move |i| TestRecord {
+                location,
+                ..TestRecord::with_event(i < events)
+            })
+        }))
+    }
+
+    #[test]
+    fn prior_keeps_the_pooled_mean() {
+        let prior = BetaPrior::fit(&[(100, 20), (100, 10), (50, 15)]).unwrap();
+        assert!((prior.alpha / (prior.alpha + prior.beta) - 45.0 / 250.0).abs() < 1e-12);
+
+        // No spread beyond binomial noise pools everything
+        let prior = BetaPrior::fit(&[(100, 10), (100, 10)]).unwrap();
+        assert!((prior.alpha + prior.beta - 200.0).abs() < 1e-9);
+
+        assert!(BetaPrior::fit(&[(100, 0), (50, 0)]).is_none());
+        assert!(BetaPrior::fit(&[]).is_none());
+    }
+
+    #[test]
+    fn small_groups_shrink_the_most() {
+        let adverse_events = adverse_events(&[("A", 100, 20), ("B", 100, 10), ("C", 4, 4)]);
+        let rates = adverse_events
+            .view()
+            .shrunken_rates(&Dimension::Location, Outcome::AnyEvent);
+        let rate = |label: &str| {
+            rates
+                .groups
+                .iter()
+                .find(|group| group.label == label)
+                .unwrap()
+        };
+
+        let mean = 34.0 / 204.0;
+        let (a, c) = (rate("A"), rate("C"));
+        assert!(c.shrunken_rate < 1.0 && c.shrunken_rate > mean);
+        assert!(c.raw_rate - c.shrunken_rate > a.raw_rate - a.shrunken_rate);
+        for group in &rates.groups {
+            assert!(group.credible_interval.lower < group.shrunken_rate);
+            assert!(group.credible_interval.upper > group.shrunken_rate);
+        }
+    }
+
+    #[test]
+    fn rates_without_a_prior_stay_raw() {
+        let adverse_events = adverse_events(&[("A", 10, 0), ("B", 5, 0)]);
+        let rates = adverse_events
+            .view()
+            .shrunken_rates(&Dimension::Location, Outcome::AnyEvent);
+
+        assert!(rates.prior.is_none());
+        assert!(rates.groups.iter().all(|group| group.shrunken_rate == 0.0));
+    }
+}
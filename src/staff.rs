@@ -0,0 +1,234 @@
+use super::*;
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StaffAttribution {
+    // Every staff member on a case is credited with the whole case
+    AnyInvolvement,
+    // Each case is split evenly between the staff members on it
+    Fractional,
+}
+
+impl FromStr for StaffAttribution {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(StaffAttribution::AnyInvolvement),
+            "fractional" => Ok(StaffAttribution::Fractional),
+            x => Err(crate::Error::ParseError {
+                type_name: "StaffAttribution",
+                received: x.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct StaffRate {
+    pub name: String,
+    pub cases: f64,
+    pub with_outcome: f64,
+    pub rate: f64,
+    // Only available with whole-case attribution
+    pub ci: Option<ConfidenceInterval>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct StaffPairing {
+    pub anesthesiologist: String,
+    pub staff: String,
+    pub cases: usize,
+    pub with_outcome: usize,
+    pub rate: f64,
+    pub ci: ConfidenceInterval,
+}
+
+impl AdverseEventRecord {
+    /// Distinct, non-blank names from the Anesthesia Staff list.
+    pub fn staff(&self) -> Vec<&str> {
+        let mut staff: Vec<&str> = Vec::new();
+        for name in &self.anesthesia_staff {
+            let name = name.trim();
+            if !name.is_empty() && !staff.contains(&name) {
+                staff.push(name);
+            }
+        }
+
+        staff
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn staff_rates(&self, outcome: &Outcome, attribution: StaffAttribution) -> Vec<StaffRate> {
+        let mut totals: HashMap<&str, (f64, f64)> = HashMap::new();
+
+        for record in &self.records {
+            let staff = record.staff();
+            let share = match attribution {
+                StaffAttribution::AnyInvolvement => 1.0,
+                StaffAttribution::Fractional => 1.0 / staff.len() as f64,
+            };
+            let occurred = outcome.occurred(record);
+
+            for name in staff {
+                let (cases, with_outcome) = totals.entry(name).or_default();
+                *cases += share;
+                if occurred {
+                    *with_outcome += share;
+                }
+            }
+        }
+
+        sort_map(totals)
+            .into_iter()
+            .map(|(name, (cases, with_outcome))| StaffRate {
+                name: name.to_string(),
+                cases,
+                with_outcome,
+                rate: with_outcome / cases,
+                ci: match attribution {
+                    StaffAttribution::AnyInvolvement => {
+                        Some(wilson_ci(with_outcome as usize, cases as usize, Z_95))
+                    }
+                    StaffAttribution::Fractional => None,
+                },
+            })
+            .collect()
+    }
+
+    pub fn staff_pairings(&self, outcome: &Outcome, min_cases: usize) -> Vec<StaffPairing> {
+        let mut pairings: HashMap<(&str, &str), (usize, usize)> = HashMap::new();
+
+        for record in &self.records {
+            let occurred = outcome.occurred(record);
+            for staff in record.staff() {
+                if staff == record.anesthesiologist {
+                    continue;
+                }
+
+                let (cases, with_outcome) = pairings
+                    .entry((record.anesthesiologist.as_str(), staff))
+                    .or_default();
+                *cases += 1;
+                if occurred {
+                    *with_outcome += 1;
+                }
+            }
+        }
+
+        let mut pairings: Vec<StaffPairing> = pairings
+            .into_iter()
+            .filter(|(_, (cases, _))| *cases >= min_cases)
+            .map(
+                |((anesthesiologist, staff), (cases, with_outcome))| StaffPairing {
+                    anesthesiologist: anesthesiologist.to_string(),
+                    staff: staff.to_string(),
+                    cases,
+                    with_outcome,
+                    rate: with_outcome as f64 / cases as f64,
+                    ci: wilson_ci(with_outcome, cases, Z_95),
+                },
+            )
+            .collect();
+
+        pairings.sort_by(|a, b| {
+            b.cases
+                .cmp(&a.cases)
+                .then_with(|| a.anesthesiologist.cmp(&b.anesthesiologist))
+                .then_with(|| a.staff.cmp(&b.staff))
+        });
+
+        pairings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    fn adverse_events() -> AdverseEvents {
+        test_data::adverse_events(vec![
+            TestRecord {
+                anesthesia_staff: "CRNA X\nCRNA Y",
+                ..TestRecord::with_event(true)
+            },
+            TestRecord {
+                anesthesia_staff: "CRNA X",
+                ..TestRecord::with_event(false)
+            },
+            // Names repeated on a case, or the attending listed as staff
+            TestRecord {
+                anesthesiologist: "Dr B",
+                anesthesia_staff: "CRNA X\nCRNA X\nDr B",
+                ..TestRecord::with_event(true)
+            },
+            TestRecord::with_event(false),
+        ])
+    }
+
+    #[test]
+    fn staff_rates_by_attribution() {
+        let adverse_events = adverse_events();
+        let view = adverse_events.view();
+        assert_eq!(view.records[2].staff(), ["CRNA X", "Dr B"]);
+
+        let shares = |attribution| -> Vec<(String, f64, f64)> {
+            view.staff_rates(&Outcome::AnyEvent, attribution)
+                .into_iter()
+                .map(|rate| (rate.name, rate.cases, rate.with_outcome))
+                .collect()
+        };
+        assert_eq!(
+            shares(StaffAttribution::AnyInvolvement),
+            [
+                ("CRNA X".to_string(), 3.0, 2.0),
+                ("CRNA Y".to_string(), 1.0, 1.0),
+                ("Dr B".to_string(), 1.0, 1.0),
+            ]
+        );
+        assert_eq!(
+            shares(StaffAttribution::Fractional),
+            [
+                ("CRNA X".to_string(), 2.0, 1.0),
+                ("CRNA Y".to_string(), 0.5, 0.5),
+                ("Dr B".to_string(), 0.5, 0.5),
+            ]
+        );
+
+        let rates = view.staff_rates(&Outcome::AnyEvent, StaffAttribution::Fractional);
+        assert!(rates.iter().all(|rate| rate.ci.is_none()));
+    }
+
+    #[test]
+    fn staff_pairings_leave_out_the_attending() {
+        let adverse_events = adverse_events();
+        let view = adverse_events.view();
+
+        let pairings = view.staff_pairings(&Outcome::AnyEvent, 1);
+        let pairings: Vec<(&str, &str, usize, usize)> = pairings
+            .iter()
+            .map(|pairing| {
+                (
+                    pairing.anesthesiologist.as_str(),
+                    pairing.staff.as_str(),
+                    pairing.cases,
+                    pairing.with_outcome,
+                )
+            })
+            .collect();
+        assert_eq!(
+            pairings,
+            [
+                ("Dr A", "CRNA X", 2, 1),
+                ("Dr A", "CRNA Y", 1, 1),
+                ("Dr B", "CRNA X", 1, 1)
+            ]
+        );
+        assert_eq!(view.staff_pairings(&Outcome::AnyEvent, 2).len(), 1);
+    }
+}
@@ -0,0 +1,254 @@
+use super::*;
+
+const DEFAULT_AGE_EDGES: [f64; 4] = [18.0, 40.0, 65.0, 80.0];
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct StratumCounts {
+    pub cases: usize,
+    pub events: usize,
+}
+
+impl StratumCounts {
+    pub fn rate(&self) -> f64 {
+        if self.cases == 0 {
+            0.0
+        } else {
+            self.events as f64 / self.cases as f64
+        }
+    }
+}
+
+/// Case mix and outcome rates of a reference population, stratified by ASA
+/// class and age band.
+#[derive(Debug, Clone)]
+pub struct ReferencePopulation {
+    pub outcome: Outcome,
+    pub age_bins: Vec<Bin>,
+    pub strata: HashMap<(u8, usize), StratumCounts>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct StandardizedRate {
+    pub label: String,
+    pub cases: usize,
+    pub events: usize,
+    pub crude_rate: f64,
+    pub standardized_rate: f64,
+    pub ci: ConfidenceInterval,
+    // Share of the reference population in strata this group has cases in;
+    // below 1 the standardized rate only reflects those strata
+    pub reference_coverage: f64,
+}
+
+impl ReferencePopulation {
+    pub fn from_view(view: &AdverseEventsView<'_>, outcome: Outcome) -> Self {
+        Self::with_age_edges(view, outcome, &DEFAULT_AGE_EDGES)
+    }
+
+    pub fn with_age_edges(
+        view: &AdverseEventsView<'_>,
+        outcome: Outcome,
+        age_edges: &[f64],
+    ) -> Self {
+        let age_bins = Binning::Edges(age_edges.to_vec()).bins(&NumericField::Age, &[]);
+        let mut reference = ReferencePopulation {
+            outcome,
+            age_bins,
+            strata: HashMap::new(),
+        };
+        reference.strata = reference.stratify(view);
+
+        reference
+    }
+
+    pub fn stratum(&self, record: &AdverseEventRecord) -> Option<(u8, usize)> {
+        let age = record.age as f64;
+        self.age_bins
+            .iter()
+            .position(|bin| bin.contains(age))
+            .map(|age_bin| (record.asa, age_bin))
+    }
+
+    fn stratify(&self, view: &AdverseEventsView<'_>) -> HashMap<(u8, usize), StratumCounts> {
+        let mut strata: HashMap<(u8, usize), StratumCounts> = HashMap::new();
+
+        for record in &view.records {
+            if let Some(stratum) = self.stratum(record) {
+                let counts = strata.entry(stratum).or_default();
+                counts.cases += 1;
+                if self.outcome.occurred(record) {
+                    counts.events += 1;
+                }
+            }
+        }
+
+        strata
+    }
+
+    /// Direct standardization: the group's stratum-specific rates weighted by
+    /// the reference population's case mix.
+    pub fn direct(&self, label: String, view: &AdverseEventsView<'_>) -> StandardizedRate {
+        let strata = self.stratify(view);
+        let reference_total: usize = self.strata.values().map(|counts| counts.cases).sum();
+        let covered_total: usize = self
+            .strata
+            .iter()
+            .filter(|(stratum, _)| strata.contains_key(stratum))
+            .map(|(_, counts)| counts.cases)
+            .sum();
+
+        let mut standardized_rate = 0.0;
+        let mut variance = 0.0;
+        for (stratum, reference) in &self.strata {
+            if let Some(counts) = strata.get(stratum) {
+                let weight = reference.cases as f64 / covered_total as f64;
+                let rate = counts.rate();
+                standardized_rate += weight * rate;
+                variance += weight * weight * rate * (1.0 - rate) / counts.cases as f64;
+            }
+        }
+
+        let cases = view.len();
+        let events = view.count_outcome(&self.outcome);
+        let spread = Z_95 * variance.sqrt();
+
+        StandardizedRate {
+            label,
+            cases,
+            events,
+            crude_rate: if cases == 0 {
+                0.0
+            } else {
+                events as f64 / cases as f64
+            },
+            standardized_rate,
+            ci: ConfidenceInterval {
+                lower: (standardized_rate - spread).max(0.0),
+                upper: (standardized_rate + spread).min(1.0),
+            },
+            reference_coverage: if reference_total == 0 {
+                0.0
+            } else {
+                covered_total as f64 / reference_total as f64
+            },
+        }
+    }
+
+    /// Indirect standardization: observed events against those expected if
+    /// the reference population's stratum-specific rates applied, i.e. the
+    /// standardized incidence ratio.
+    pub fn indirect(&self, label: String, view: &AdverseEventsView<'_>) -> ObservedExpected {
+        let observed = view.count_outcome(&self.outcome);
+        let expected: f64 = self
+            .stratify(view)
+            .iter()
+            .map(|(stratum, counts)| {
+                counts.cases as f64 * self.strata.get(stratum).map_or(0.0, |r| r.rate())
+            })
+            .sum();
+        ObservedExpected::new(label, view.len(), observed, expected)
+    }
+
+    pub fn direct_groups(&self, groups: &[DimensionGroup<'_>]) -> Vec<StandardizedRate> {
+        groups
+            .iter()
+            .map(|group| self.direct(group.label.clone(), &group.value))
+            .collect()
+    }
+
+    pub fn indirect_groups(&self, groups: &[DimensionGroup<'_>]) -> Vec<ObservedExpected> {
+        groups
+            .iter()
+            .map(|group| self.indirect(group.label.clone(), &group.value))
+            .collect()
+    }
+
+    pub fn direct_periods(
+        &self,
+        periods: &[DatePeriodView<'_>],
+    ) -> Vec<DatePeriodContainer<StandardizedRate>> {
+        periods
+            .iter()
+            .map(|dpv| DatePeriodContainer {
+                period: dpv.period,
+                start: dpv.start,
+                end: dpv.end,
+                value: self.direct(dpv.start.to_string(), &dpv.value),
+            })
+            .collect()
+    }
+
+    pub fn indirect_periods(
+        &self,
+        periods: &[DatePeriodView<'_>],
+    ) -> Vec<DatePeriodContainer<ObservedExpected>> {
+        periods
+            .iter()
+            .map(|dpv| DatePeriodContainer {
+                period: dpv.period,
+                start: dpv.start,
+                end: dpv.end,
+                value: self.indirect(dpv.start.to_string(), &dpv.value),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    // (ASA, age, cases, events)
+    fn adverse_events(strata: &[(u8, u8, usize, usize)]) -> AdverseEvents {
+        test_data::adverse_events(strata.iter().flat_map(|&(asa, age, cases, events)| {
+            (0..cases).map(move |i| TestRecord {
+                asa,
+                age,
+                ..TestRecord::with_event(i < events)
+            })
+        }))
+    }
+
+    fn reference() -> AdverseEvents {
+        adverse_events(&[(1, 30, 100, 10), (3, 70, 100, 30)])
+    }
+
+    #[test]
+    fn direct_weights_by_reference_case_mix() {
+        let reference_events = reference();
+        let reference = ReferencePopulation::from_view(&reference_events.view(), Outcome::AnyEvent);
+        let group = adverse_events(&[(1, 30, 10, 2), (3, 70, 90, 36)]);
+
+        let rate = reference.direct("Group".to_string(), &group.view());
+        assert!((rate.crude_rate - 0.38).abs() < 1e-12);
+        assert!((rate.standardized_rate - 0.3).abs() < 1e-12);
+        assert_eq!(rate.reference_coverage, 1.0);
+        assert!(rate.ci.lower < 0.3 && rate.ci.upper > 0.3);
+    }
+
+    #[test]
+    fn direct_reports_partial_coverage() {
+        let reference_events = reference();
+        let reference = ReferencePopulation::from_view(&reference_events.view(), Outcome::AnyEvent);
+        let group = adverse_events(&[(1, 30, 10, 2)]);
+
+        let rate = reference.direct("Group".to_string(), &group.view());
+        assert!((rate.standardized_rate - 0.2).abs() < 1e-12);
+        assert_eq!(rate.reference_coverage, 0.5);
+    }
+
+    #[test]
+    fn indirect_uses_reference_rates() {
+        let reference_events = reference();
+        let reference = ReferencePopulation::from_view(&reference_events.view(), Outcome::AnyEvent);
+        let group = adverse_events(&[(1, 30, 10, 2), (3, 70, 90, 36)]);
+
+        let ratio = reference.indirect("Group".to_string(), &group.view());
+        assert_eq!(ratio.observed, 38);
+        assert!((ratio.expected - 28.0).abs() < 1e-12);
+        assert!((ratio.ratio.unwrap() - 38.0 / 28.0).abs() < 1e-12);
+    }
+}
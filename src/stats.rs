@@ -0,0 +1,425 @@
+use super::*;
+
+pub const Z_95: f64 = 1.959_963_984_540_054;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConfidenceInterval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// Inverse of the standard normal CDF (Acklam's rational approximation,
+/// relative error below 1.2e-9).
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    if p <= 0.0 {
+        f64::NEG_INFINITY
+    } else if p >= 1.0 {
+        f64::INFINITY
+    } else if p < P_LOW {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -normal_quantile(1.0 - p)
+    }
+}
+
+/// Two-sided critical value for the given confidence level, e.g. 0.95.
+pub fn z_for_confidence(confidence: f64) -> f64 {
+    normal_quantile(1.0 - (1.0 - confidence) / 2.0)
+}
+
+/// Confidence interval for a Poisson count using Byar's approximation.
+pub fn poisson_ci(observed: usize, z: f64) -> ConfidenceInterval {
+    let o = observed as f64;
+
+    let lower = if observed == 0 {
+        0.0
+    } else {
+        o * (1.0 - 1.0 / (9.0 * o) - z / (3.0 * o.sqrt())).powi(3)
+    };
+
+    let o1 = o + 1.0;
+    let upper = o1 * (1.0 - 1.0 / (9.0 * o1) + z / (3.0 * o1.sqrt())).powi(3);
+
+    ConfidenceInterval {
+        lower: lower.max(0.0),
+        upper,
+    }
+}
+
+/// Wilson score interval for a binomial proportion.
+pub fn wilson_ci(successes: usize, total: usize, z: f64) -> ConfidenceInterval {
+    if total == 0 {
+        return ConfidenceInterval {
+            lower: 0.0,
+            upper: 1.0,
+        };
+    }
+
+    let n = total as f64;
+    let p = successes as f64 / n;
+    let z2 = z * z;
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let spread = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+
+    ConfidenceInterval {
+        lower: (center - spread).max(0.0),
+        upper: (center + spread).min(1.0),
+    }
+}
+
+pub fn logistic(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Inverts a small dense matrix with Gauss-Jordan elimination, returning
+/// `None` if it is singular.
+pub fn invert_matrix(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut a: Vec<Vec<f64>> = matrix.to_vec();
+    let mut inv: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&x, &y| {
+            a[x][col]
+                .abs()
+                .partial_cmp(&a[y][col].abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+
+        let divisor = a[col][col];
+        for j in 0..n {
+            a[col][j] /= divisor;
+            inv[col][j] /= divisor;
+        }
+
+        for row in 0..n {
+            if row != col {
+                let factor = a[row][col];
+                if factor != 0.0 {
+                    for j in 0..n {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+    }
+
+    Some(inv)
+}
+
+/// Lanczos approximation of ln Γ(x) for x > 0.
+pub fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.120_865_097_386_617_9e-2,
+        -0.539_523_938_495_3e-5,
+    ];
+
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000_000_000_190_015;
+    let mut y = x;
+    for coefficient in COEFFICIENTS.iter() {
+        y += 1.0;
+        series += coefficient / y;
+    }
+
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// Complementary error function (Chebyshev approximation, fractional error
+/// below 1.2e-7).
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let ans = t
+        * (-z * z - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98
+                                    + t * (1.488_515_87
+                                        + t * (-0.822_152_23 + t * 0.170_872_77)))))))))
+            .exp();
+
+    if x >= 0.0 {
+        ans
+    } else {
+        2.0 - ans
+    }
+}
+
+pub fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / std::f64::consts::SQRT_2)
+}
+
+/// Upper tail probability of a chi-square statistic with one degree of
+/// freedom.
+pub fn chi_square_p_value(statistic: f64) -> f64 {
+    erfc((statistic / 2.0).sqrt())
+}
+
+/// Two-sided Fisher's exact test for the 2×2 table `[[a, b], [c, d]]`.
+pub fn fisher_exact_p_value(a: usize, b: usize, c: usize, d: usize) -> f64 {
+    let row1 = a + b;
+    let row2 = c + d;
+    let col1 = a + c;
+    let n = row1 + row2;
+
+    let ln_factorial = |k: usize| ln_gamma(k as f64 + 1.0);
+    let fixed =
+        ln_factorial(row1) + ln_factorial(row2) + ln_factorial(col1) + ln_factorial(n - col1)
+            - ln_factorial(n);
+    let probability = |x: usize| {
+        (fixed
+            - ln_factorial(x)
+            - ln_factorial(row1 - x)
+            - ln_factorial(col1 - x)
+            - ln_factorial(row2 + x - col1))
+        .exp()
+    };
+
+    let observed = probability(a);
+    let min = col1.saturating_sub(row2);
+    let max = row1.min(col1);
+
+    (min..=max)
+        .map(probability)
+        .filter(|p| *p <= observed * (1.0 + 1e-7))
+        .sum::<f64>()
+        .min(1.0)
+}
+
+/// Regularized incomplete beta function I_x(a, b), evaluated with Lentz's
+/// continued fraction.
+pub fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln();
+
+    // The continued fraction converges quickly only below this point, so
+    // use the symmetry relation above it
+    if x < (a + 1.0) / (a + b + 2.0) {
+        ln_front.exp() * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - ln_front.exp() * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 300;
+    const EPSILON: f64 = 3e-14;
+    const TINY: f64 = 1e-300;
+
+    let qab = a + b;
+    let qap = a + 1.0;
+    let qam = a - 1.0;
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        let aa = m * (b - m) * x / ((qam + m2) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+
+        let aa = -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+
+    h
+}
+
+/// Quantile of the Beta(a, b) distribution, found by bisection.
+pub fn beta_quantile(p: f64, a: f64, b: f64) -> f64 {
+    let mut low = 0.0;
+    let mut high = 1.0;
+
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if incomplete_beta(a, b, mid) < p {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    (low + high) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} isn't within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn normal_quantile_matches_tables() {
+        assert_close(normal_quantile(0.975), Z_95, 1e-6);
+        assert_close(normal_quantile(0.5), 0.0, 1e-9);
+        assert_close(normal_quantile(0.05), -1.644_854, 1e-6);
+        assert_close(z_for_confidence(0.99), 2.575_829, 1e-6);
+    }
+
+    #[test]
+    fn poisson_ci_is_close_to_exact() {
+        // Exact limits for 10 events are 4.795 and 18.390
+        let ci = poisson_ci(10, Z_95);
+        assert_close(ci.lower, 4.795, 0.02);
+        assert_close(ci.upper, 18.390, 0.02);
+
+        let ci = poisson_ci(0, Z_95);
+        assert_eq!(ci.lower, 0.0);
+        assert_close(ci.upper, 3.689, 0.05);
+    }
+
+    #[test]
+    fn wilson_ci_matches_reference() {
+        let ci = wilson_ci(5, 10, Z_95);
+        assert_close(ci.lower, 0.2366, 1e-4);
+        assert_close(ci.upper, 0.7634, 1e-4);
+
+        let ci = wilson_ci(0, 20, Z_95);
+        assert_eq!(ci.lower, 0.0);
+        assert_close(ci.upper, 0.1611, 1e-4);
+
+        let ci = wilson_ci(0, 0, Z_95);
+        assert_eq!((ci.lower, ci.upper), (0.0, 1.0));
+    }
+
+    #[test]
+    fn invert_matrix_inverts_and_detects_singular() {
+        let inverse = invert_matrix(&[vec![4.0, 7.0], vec![2.0, 6.0]]).unwrap();
+        let expected = [[0.6, -0.7], [-0.2, 0.4]];
+        for (row, expected_row) in inverse.iter().zip(&expected) {
+            for (value, expected_value) in row.iter().zip(expected_row) {
+                assert_close(*value, *expected_value, 1e-12);
+            }
+        }
+
+        assert!(invert_matrix(&[vec![1.0, 2.0], vec![2.0, 4.0]]).is_none());
+    }
+
+    #[test]
+    fn fisher_exact_p_value_matches_reference() {
+        // Fisher's tea tasting experiment
+        assert_close(fisher_exact_p_value(3, 1, 1, 3), 0.485_714, 1e-6);
+        assert_close(fisher_exact_p_value(1, 9, 11, 3), 0.002_759, 1e-6);
+        assert_close(fisher_exact_p_value(2, 2, 2, 2), 1.0, 1e-9);
+        assert_close(
+            fisher_exact_p_value(1, 9, 11, 3),
+            fisher_exact_p_value(9, 1, 3, 11),
+            1e-12,
+        );
+    }
+
+    #[test]
+    fn chi_square_p_value_matches_tables() {
+        assert_close(chi_square_p_value(3.841_459), 0.05, 1e-6);
+        assert_close(chi_square_p_value(6.634_897), 0.01, 1e-6);
+        assert_close(ln_gamma(5.0), 24f64.ln(), 1e-10);
+    }
+
+    #[test]
+    fn beta_quantile_inverts_incomplete_beta() {
+        assert_close(incomplete_beta(2.0, 3.0, 0.4), 0.5248, 1e-10);
+        assert_close(incomplete_beta(1.0, 1.0, 0.3), 0.3, 1e-10);
+        assert_close(beta_quantile(0.5248, 2.0, 3.0), 0.4, 1e-9);
+        assert_close(beta_quantile(0.5, 4.0, 4.0), 0.5, 1e-9);
+    }
+}
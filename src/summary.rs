@@ -0,0 +1,118 @@
+use super::*;
+
+use std::collections::HashSet;
+
+/// Headline figures for a view.
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct Summary<C: Serialize = usize> {
+    pub records: C,
+    pub patients: C,
+    pub first_date: Option<NaiveDate>,
+    pub last_date: Option<NaiveDate>,
+    pub with_event: C,
+    pub events: C,
+    pub complications_specified: C,
+    pub complications_occurred: C,
+    pub anesthesiologists: C,
+    pub locations: C,
+    pub anesthesia_hours: f64,
+}
+
+impl Summary {
+    /// Withholds small counts. The figures aren't parts of one total, so
+    /// there's nothing to suppress alongside them.
+    pub fn suppressed(self, rule: &SuppressionRule) -> Summary<Suppressible<usize>> {
+        let suppress = |count: usize| suppress_counts(&[count], &[], rule).remove(0);
+
+        Summary {
+            records: suppress(self.records),
+            patients: suppress(self.patients),
+            first_date: self.first_date,
+            last_date: self.last_date,
+            with_event: suppress(self.with_event),
+            events: suppress(self.events),
+            complications_specified: suppress(self.complications_specified),
+            complications_occurred: suppress(self.complications_occurred),
+            anesthesiologists: suppress(self.anesthesiologists),
+            locations: suppress(self.locations),
+            anesthesia_hours: self.anesthesia_hours,
+        }
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn summary(&self) -> Summary {
+        let distinct = |field: fn(&AdverseEventRecord) -> &str| {
+            self.records
+                .iter()
+                .map(|record| field(record))
+                .filter(|value| !value.trim().is_empty())
+                .collect::<HashSet<_>>()
+                .len()
+        };
+        let date_range = self.date_range();
+
+        Summary {
+            records: self.len(),
+            patients: distinct(|record| &record.mrn),
+            first_date: date_range.map(|(first, _)| first),
+            last_date: date_range.map(|(_, last)| last),
+            with_event: self.count(|record| !record.adverse_events.is_empty()),
+            events: self
+                .records
+                .iter()
+                .map(|record| record.adverse_events.len())
+                .sum(),
+            complications_specified: self.count(|record| record.complications.is_some()),
+            complications_occurred: self.count(|record| record.complications == Some(true)),
+            anesthesiologists: distinct(|record| &record.anesthesiologist),
+            locations: distinct(|record| &record.location),
+            anesthesia_hours: self.anesthesia_hours(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    #[test]
+    fn summary_counts_distinct_values() {
+        let record = |mrn: &str, date, anesthesiologist, location, adverse_events| TestRecord {
+            mrn: Some(mrn.to_string()),
+            date,
+            anesthesiologist,
+            location,
+            adverse_events,
+            ..Default::default()
+        };
+        let adverse_events = test_data::adverse_events(vec![
+            record("M1", "01/01/19", "Dr A", "OR", "nausea, pain"),
+            record("M1", "01/02/19", "Dr B", "PACU", ""),
+            record("M2", "01/03/19", "Dr A", "OR", "nausea"),
+        ]);
+        let summary = adverse_events.view().summary();
+
+        assert_eq!(summary.records, 3);
+        assert_eq!(summary.patients, 2);
+        assert_eq!(summary.first_date, NaiveDate::from_ymd_opt(2019, 1, 1));
+        assert_eq!(summary.last_date, NaiveDate::from_ymd_opt(2019, 1, 3));
+        assert_eq!((summary.with_event, summary.events), (2, 3));
+        assert_eq!(
+            (
+                summary.complications_specified,
+                summary.complications_occurred
+            ),
+            (3, 0)
+        );
+        assert_eq!((summary.anesthesiologists, summary.locations), (2, 2));
+        assert!((summary.anesthesia_hours - 3.0).abs() < 1e-9);
+
+        let suppressed = summary.suppressed(&SuppressionRule::new(3));
+        assert_eq!(suppressed.records, Suppressible::Shown(3));
+        assert!(suppressed.patients.is_suppressed());
+        assert_eq!(suppressed.complications_occurred, Suppressible::Shown(0));
+    }
+}
@@ -0,0 +1,339 @@
+use super::*;
+
+use std::{collections::HashSet, str::FromStr};
+
+// Two-digit years first, since `%Y` would read "19" as the year 19
+const DATE_FORMATS: [&str; 3] = ["%m/%d/%y", "%m/%d/%Y", "%Y-%m-%d"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AttributeType {
+    Text,
+    Number,
+    Bool,
+    Date,
+}
+
+impl FromStr for AttributeType {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(AttributeType::Text),
+            "number" => Ok(AttributeType::Number),
+            "bool" => Ok(AttributeType::Bool),
+            "date" => Ok(AttributeType::Date),
+            x => Err(crate::Error::ParseError {
+                type_name: "AttributeType",
+                received: x.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Date(NaiveDate),
+}
+
+impl AttributeValue {
+    /// Parses a raw cell as the given type, `Ok(None)` for a blank cell.
+    pub fn parse(attribute_type: AttributeType, raw: &str) -> Result<Option<Self>, Error> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return Ok(None);
+        }
+
+        let err = || Error::ParseError {
+            type_name: "AttributeValue",
+            received: raw.to_string(),
+        };
+
+        let value = match attribute_type {
+            AttributeType::Text => AttributeValue::Text(raw.to_string()),
+            AttributeType::Number => AttributeValue::Number(raw.parse().map_err(|_| err())?),
+            AttributeType::Bool => AttributeValue::Bool(match raw.to_lowercase().as_str() {
+                "y" | "yes" | "true" | "1" => true,
+                "n" | "no" | "false" | "0" => false,
+                _ => return Err(err()),
+            }),
+            AttributeType::Date => AttributeValue::Date(
+                DATE_FORMATS
+                    .iter()
+                    .find_map(|format| NaiveDate::parse_from_str(raw, format).ok())
+                    .ok_or_else(err)?,
+            ),
+        };
+
+        Ok(Some(value))
+    }
+}
+
+impl fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeValue::Text(text) => write!(f, "{}", text),
+            AttributeValue::Number(number) => write!(f, "{}", number),
+            AttributeValue::Bool(true) => write!(f, "Yes"),
+            AttributeValue::Bool(false) => write!(f, "No"),
+            AttributeValue::Date(date) => write!(f, "{}", date),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupplementColumn {
+    // Header in the supplementary CSV
+    pub column: String,
+    // Attribute name to attach it as, the header if not given
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub attribute_type: AttributeType,
+}
+
+impl FromStr for SupplementColumn {
+    type Err = crate::Error;
+
+    /// Parses `<column>=<type>` or `<column>=<type>:<name>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || crate::Error::ParseError {
+            type_name: "SupplementColumn",
+            received: s.to_string(),
+        };
+
+        let i = s.rfind('=').ok_or_else(err)?;
+        let (attribute_type, name) = match s[i + 1..].find(':') {
+            Some(j) => (&s[i + 1..i + 1 + j], Some(s[i + 2 + j..].to_string())),
+            None => (&s[i + 1..], None),
+        };
+
+        Ok(SupplementColumn {
+            column: s[..i].trim().to_string(),
+            name,
+            attribute_type: AttributeType::from_str(attribute_type).map_err(|_| err())?,
+        })
+    }
+}
+
+fn default_key_column() -> String {
+    "Episode ID".to_string()
+}
+
+/// Declares which columns of a supplementary extract to join, and as what
+/// types. Columns not listed are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupplementConfig {
+    #[serde(default = "default_key_column")]
+    pub key_column: String,
+    pub columns: Vec<SupplementColumn>,
+}
+
+impl SupplementConfig {
+    pub fn new(columns: Vec<SupplementColumn>) -> Self {
+        SupplementConfig {
+            key_column: default_key_column(),
+            columns,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct InvalidValue {
+    pub episode_id: String,
+    pub column: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct JoinReport {
+    pub matched: usize,
+    // Episodes in the data set without a row in the extract
+    pub unmatched_records: Vec<String>,
+    // Rows in the extract without an episode in the data set
+    pub unmatched_keys: Vec<String>,
+    // Keys appearing more than once in the extract; the last row is used
+    pub duplicate_keys: Vec<String>,
+    // Cells that couldn't be read as their declared type, left unset
+    pub invalid_values: Vec<InvalidValue>,
+}
+
+impl AdverseEvents {
+    pub fn join_supplement<R>(
+        &mut self,
+        config: &SupplementConfig,
+        reader: R,
+    ) -> Result<JoinReport, Error>
+    where
+        R: Read,
+    {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+
+        let headers = reader.headers()?.clone();
+        let position = |column: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim() == column.trim())
+                .ok_or_else(|| Error::ParseError {
+                    type_name: "SupplementColumn",
+                    received: column.to_string(),
+                })
+        };
+
+        let key_position = position(&config.key_column)?;
+        let columns = config
+            .columns
+            .iter()
+            .map(|column| Ok((column, position(&column.column)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut invalid_values = Vec::new();
+        let mut duplicate_keys = Vec::new();
+        let mut rows: HashMap<String, Vec<(String, AttributeValue)>> = HashMap::new();
+
+        for row in reader.records() {
+            let row = row?;
+            let key = match row.get(key_position).map(str::trim) {
+                Some(key) if !key.is_empty() => key.to_string(),
+                _ => continue,
+            };
+
+            let mut values = Vec::new();
+            for (column, i) in &columns {
+                let raw = row.get(*i).unwrap_or("");
+                match AttributeValue::parse(column.attribute_type, raw) {
+                    Ok(Some(value)) => values.push((
+                        column.name.clone().unwrap_or_else(|| column.column.clone()),
+                        value,
+                    )),
+                    Ok(None) => {}
+                    Err(_) => invalid_values.push(InvalidValue {
+                        episode_id: key.clone(),
+                        column: column.column.clone(),
+                        value: raw.to_string(),
+                    }),
+                }
+            }
+
+            if rows.insert(key.clone(), values).is_some() {
+                duplicate_keys.push(key);
+            }
+        }
+
+        let mut matched = 0;
+        let mut unmatched_records = Vec::new();
+        let mut joined: HashSet<&str> = HashSet::new();
+
+        for record in &mut self.records {
+            let key = record.episode_id.trim();
+            match rows.get_key_value(key) {
+                Some((key, values)) => {
+                    matched += 1;
+                    for (name, value) in values {
+                        record.attributes.insert(name.clone(), value.clone());
+                    }
+                    joined.insert(key);
+                }
+                None => unmatched_records.push(key.to_string()),
+            }
+        }
+
+        let mut unmatched_keys: Vec<String> = rows
+            .keys()
+            .filter(|key| !joined.contains(key.as_str()))
+            .cloned()
+            .collect();
+        unmatched_keys.sort();
+        duplicate_keys.sort();
+        duplicate_keys.dedup();
+
+        Ok(JoinReport {
+            matched,
+            unmatched_records,
+            unmatched_keys,
+            duplicate_keys,
+            invalid_values,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    #[test]
+    fn parse_reads_two_and_four_digit_years() {
+        let date = Some(AttributeValue::Date(
+            NaiveDate::from_ymd_opt(2019, 3, 10).unwrap(),
+        ));
+
+        for raw in ["03/10/19", "03/10/2019", "2019-03-10"] {
+            assert_eq!(
+                AttributeValue::parse(AttributeType::Date, raw).unwrap(),
+                date
+            );
+        }
+        assert!(AttributeValue::parse(AttributeType::Date, "10 March").is_err());
+    }
+
+    #[test]
+    fn parse_handles_blanks_and_bools() {
+        assert_eq!(
+            AttributeValue::parse(AttributeType::Number, " ").unwrap(),
+            None
+        );
+        assert_eq!(
+            AttributeValue::parse(AttributeType::Bool, "Yes").unwrap(),
+            Some(AttributeValue::Bool(true))
+        );
+        assert!(AttributeValue::parse(AttributeType::Bool, "maybe").is_err());
+        assert!(AttributeValue::parse(AttributeType::Number, "n/a").is_err());
+    }
+
+    #[test]
+    fn supplement_column_from_str() {
+        let column: SupplementColumn = "Case Class=text:class".parse().unwrap();
+        assert_eq!(column.column, "Case Class");
+        assert_eq!(column.name.as_deref(), Some("class"));
+        assert_eq!(column.attribute_type, AttributeType::Text);
+        assert!("Case Class".parse::<SupplementColumn>().is_err());
+        assert!("Case Class=colour".parse::<SupplementColumn>().is_err());
+    }
+
+    #[test]
+    fn join_supplement_reports_mismatches() {
+        let mut adverse_events = test_data::adverse_events(vec![
+            TestRecord {
+                episode_id: Some("E1".to_string()),
+                ..Default::default()
+            },
+            TestRecord {
+                episode_id: Some("E2".to_string()),
+                ..Default::default()
+            },
+        ]);
+        let config = SupplementConfig::new(vec!["Blood Loss=number".parse().unwrap()]);
+        let extract = "Episode ID,Blood Loss\nE1,oops\nE1,250\nE3,100\n";
+
+        let report = adverse_events
+            .join_supplement(&config, extract.as_bytes())
+            .unwrap();
+        assert_eq!(report.matched, 1);
+        assert_eq!(report.unmatched_records, ["E2"]);
+        assert_eq!(report.unmatched_keys, ["E3"]);
+        assert_eq!(report.duplicate_keys, ["E1"]);
+        assert_eq!(report.invalid_values.len(), 1);
+        assert_eq!(
+            adverse_events.records[0].attributes.get("Blood Loss"),
+            Some(&AttributeValue::Number(250.0))
+        );
+    }
+}
@@ -0,0 +1,725 @@
+use super::*;
+
+use serde::Serializer;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SuppressionRule {
+    // Counts below this are suppressed
+    pub threshold: usize,
+    // Whether zero counts are suppressed too
+    pub suppress_zero: bool,
+}
+
+impl SuppressionRule {
+    pub fn new(threshold: usize) -> Self {
+        SuppressionRule {
+            threshold,
+            suppress_zero: false,
+        }
+    }
+
+    fn is_small(&self, count: usize) -> bool {
+        count < self.threshold && (count > 0 || self.suppress_zero)
+    }
+}
+
+impl Default for SuppressionRule {
+    fn default() -> Self {
+        Self::new(11)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SuppressionReason {
+    // Below the threshold
+    SmallCell(usize),
+    // Hidden so a small cell can't be worked out from the totals
+    Complementary,
+}
+
+/// A published value, or a marker saying why it was withheld. Serializes as
+/// the value itself, or `"<11"` / `"suppressed"` in its place.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Suppressible<T> {
+    Shown(T),
+    Suppressed(SuppressionReason),
+}
+
+impl<T> Suppressible<T> {
+    pub fn is_suppressed(&self) -> bool {
+        matches!(self, Suppressible::Suppressed(_))
+    }
+
+    pub fn map<U, F>(self, f: F) -> Suppressible<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        match self {
+            Suppressible::Shown(value) => Suppressible::Shown(f(value)),
+            Suppressible::Suppressed(reason) => Suppressible::Suppressed(reason),
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Suppressible<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Suppressible::Shown(value) => write!(f, "{}", value),
+            Suppressible::Suppressed(SuppressionReason::SmallCell(threshold)) => {
+                write!(f, "<{}", threshold)
+            }
+            Suppressible::Suppressed(SuppressionReason::Complementary) => write!(f, "suppressed"),
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Suppressible<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Suppressible::Shown(value) => value.serialize(serializer),
+            Suppressible::Suppressed(SuppressionReason::SmallCell(threshold)) => {
+                serializer.collect_str(&format_args!("<{}", threshold))
+            }
+            Suppressible::Suppressed(SuppressionReason::Complementary) => {
+                serializer.serialize_str("suppressed")
+            }
+        }
+    }
+}
+
+/// Suppresses small counts, then keeps suppressing more until no line
+/// (a set of cells whose sum is published or derivable) has exactly one
+/// suppressed cell, since that cell could be recovered by subtraction.
+/// The smallest remaining non-zero cell in the line is taken as the
+/// complement, since hiding a zero alongside it hides little. Lines list
+/// their total as a cell where it's published, so the total is taken when
+/// only zeros are left.
+pub fn suppress_counts(
+    counts: &[usize],
+    lines: &[Vec<usize>],
+    rule: &SuppressionRule,
+) -> Vec<Suppressible<usize>> {
+    let mut cells: Vec<Suppressible<usize>> = counts
+        .iter()
+        .map(|&count| {
+            if rule.is_small(count) {
+                Suppressible::Suppressed(SuppressionReason::SmallCell(rule.threshold))
+            } else {
+                Suppressible::Shown(count)
+            }
+        })
+        .collect();
+
+    loop {
+        let mut changed = false;
+
+        for line in lines {
+            let suppressed = line.iter().filter(|&&i| cells[i].is_suppressed()).count();
+            if suppressed != 1 {
+                continue;
+            }
+
+            let complement = line
+                .iter()
+                .copied()
+                .filter(|&i| !cells[i].is_suppressed())
+                .min_by_key(|&i| (counts[i] == 0, counts[i]));
+            if let Some(i) = complement {
+                cells[i] = Suppressible::Suppressed(SuppressionReason::Complementary);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return cells;
+        }
+    }
+}
+
+/// Suppresses a line of counts along with the total printed beside it. A
+/// line with no other cell to hide alongside a small one has its total
+/// withheld instead, since the small cell is the total minus the rest.
+pub fn suppress_with_total(
+    counts: &[usize],
+    rule: &SuppressionRule,
+) -> (Vec<Suppressible<usize>>, Suppressible<usize>) {
+    let mut values = counts.to_vec();
+    values.push(counts.iter().sum());
+    let line: Vec<usize> = (0..values.len()).collect();
+
+    let mut cells = suppress_counts(&values, &[line], rule);
+    let total = cells.pop().expect("the total is always a cell");
+    (cells, total)
+}
+
+#[derive(Debug, Serialize)]
+pub struct LabeledCell {
+    pub label: String,
+    pub value: Suppressible<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct SuppressedStratum<T: Serialize = LabeledCell> {
+    pub label: String,
+    pub total: Suppressible<usize>,
+    pub breakdown: Vec<T>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub struct SuppressedRate {
+    pub label: String,
+    pub total: Suppressible<usize>,
+    pub with_outcome: Suppressible<usize>,
+    pub rate: Suppressible<f64>,
+    pub ci: Suppressible<ConfidenceInterval>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum SuppressedTimeseries {
+    Counts(Vec<DatePeriodContainer<Suppressible<usize>>>),
+    // Percentages and events per hundred hours
+    Rates(Vec<DatePeriodContainer<Suppressible<f64>>>),
+    // Hours aren't counts of people, so there is nothing to suppress
+    Hours(Vec<DatePeriodHours>),
+}
+
+impl From<Timeseries> for SuppressedTimeseries {
+    /// Wraps every value as shown.
+    fn from(timeseries: Timeseries) -> Self {
+        let shown = |dpc: DatePeriodContainer<f64>| DatePeriodContainer {
+            period: dpc.period,
+            start: dpc.start,
+            end: dpc.end,
+            value: Suppressible::Shown(dpc.value),
+        };
+
+        match timeseries {
+            Timeseries::Counts(counts) => SuppressedTimeseries::Counts(
+                counts
+                    .into_iter()
+                    .map(|dpc| DatePeriodContainer {
+                        period: dpc.period,
+                        start: dpc.start,
+                        end: dpc.end,
+                        value: Suppressible::Shown(dpc.value),
+                    })
+                    .collect(),
+            ),
+            Timeseries::Percentages(percentages) => {
+                SuppressedTimeseries::Rates(percentages.into_iter().map(shown).collect())
+            }
+            Timeseries::ExposureRates(rates) => SuppressedTimeseries::Rates(
+                rates
+                    .into_iter()
+                    .map(|dpe| DatePeriodContainer {
+                        period: dpe.period,
+                        start: dpe.start,
+                        end: dpe.end,
+                        value: Suppressible::Shown(dpe.value.rate),
+                    })
+                    .collect(),
+            ),
+            Timeseries::Hours(hours) => SuppressedTimeseries::Hours(hours),
+        }
+    }
+}
+
+/// Suppresses a flat list of counts whose total is published. The total is
+/// a cell of the line, so it's hidden as the complement before a zero is.
+pub fn suppress_labeled_counts(
+    counts: Vec<LabeledCount>,
+    rule: &SuppressionRule,
+) -> Vec<LabeledCell> {
+    let values: Vec<usize> = counts.iter().map(|count| count.value).collect();
+    let (cells, _) = suppress_with_total(&values, rule);
+
+    counts
+        .into_iter()
+        .zip(cells)
+        .map(|(count, value)| LabeledCell {
+            label: count.label,
+            value,
+        })
+        .collect()
+}
+
+/// Suppresses group sizes and outcome counts across a list of rates. Each
+/// rate's cases with and without the outcome add up to its total, and each
+/// of the three adds up across the rates to an overall count. A rate is
+/// withheld along with any of its counts, since the rate and one count give
+/// the others away.
+pub fn suppress_rates(rates: Vec<LabeledRate>, rule: &SuppressionRule) -> Vec<SuppressedRate> {
+    let n = rates.len();
+    // Totals, then with the outcome, then without, each followed by its
+    // overall count
+    let mut counts = Vec::with_capacity(3 * (n + 1));
+    for count in [
+        |rate: &LabeledRate| rate.total,
+        |rate: &LabeledRate| rate.with_outcome,
+        |rate: &LabeledRate| rate.total.saturating_sub(rate.with_outcome),
+    ] {
+        counts.extend(rates.iter().map(count));
+        counts.push(rates.iter().map(count).sum());
+    }
+
+    let cell = |column: usize, row: usize| column * (n + 1) + row;
+    let mut lines: Vec<Vec<usize>> = (0..=n)
+        .map(|row| vec![cell(1, row), cell(2, row), cell(0, row)])
+        .collect();
+    lines.extend((0..3).map(|column| (0..=n).map(|row| cell(column, row)).collect()));
+    let cells = suppress_counts(&counts, &lines, rule);
+
+    rates
+        .into_iter()
+        .enumerate()
+        .map(|(row, rate)| {
+            let (total, with_outcome) = (cells[cell(0, row)], cells[cell(1, row)]);
+            let withheld = [total, with_outcome, cells[cell(2, row)]]
+                .iter()
+                .find_map(|cell| match cell {
+                    Suppressible::Suppressed(reason) => Some(*reason),
+                    Suppressible::Shown(_) => None,
+                });
+
+            match withheld {
+                Some(reason) => SuppressedRate {
+                    label: rate.label,
+                    total,
+                    with_outcome,
+                    rate: Suppressible::Suppressed(reason),
+                    ci: Suppressible::Suppressed(reason),
+                },
+                None => SuppressedRate {
+                    label: rate.label,
+                    total,
+                    with_outcome,
+                    rate: Suppressible::Shown(rate.rate),
+                    ci: Suppressible::Shown(rate.ci),
+                },
+            }
+        })
+        .collect()
+}
+
+impl<'a> AdverseEventsView<'a> {
+    /// Event counts in the same order as `sort_map(event_counts())`, and
+    /// their total.
+    pub fn suppressed_event_counts(
+        &self,
+        rule: &SuppressionRule,
+    ) -> (Vec<(&str, Suppressible<usize>)>, Suppressible<usize>) {
+        let counts = sort_map(self.event_counts());
+        let values: Vec<usize> = counts.iter().map(|(_, count)| *count as usize).collect();
+        let (cells, total) = suppress_with_total(&values, rule);
+
+        (
+            counts
+                .into_iter()
+                .map(|(event, _)| event)
+                .zip(cells)
+                .collect(),
+            total,
+        )
+    }
+
+    pub fn suppressed_breakdown(
+        &self,
+        breakdown_type: BreakdownType,
+        rule: &SuppressionRule,
+    ) -> Vec<LabeledCell> {
+        suppress_labeled_counts(
+            self.get_breakdown(breakdown_type, None)
+                .into_iter()
+                .flat_map(|stratum| stratum.breakdown)
+                .collect(),
+            rule,
+        )
+    }
+
+    pub fn suppressed_group_counts(
+        &self,
+        dimension: &Dimension,
+        rule: &SuppressionRule,
+    ) -> Vec<LabeledCell> {
+        suppress_labeled_counts(
+            self.group_by_dimension(dimension)
+                .into_iter()
+                .map(|group| LabeledCount {
+                    label: group.label,
+                    value: group.value.len(),
+                })
+                .collect(),
+            rule,
+        )
+    }
+
+    /// Suppresses a stratified breakdown as a crosstab. Each stratum's
+    /// total is treated as one more column, every row and column is a line
+    /// for complementary suppression, and the grand total stays published.
+    ///
+    /// Patient characteristics only count records with an event, so their
+    /// rows add up to the stratum's event count rather than its total. That
+    /// count is published elsewhere, so the categories alone are the line.
+    pub fn suppressed_stratified_breakdown(
+        &self,
+        breakdown_type: BreakdownType,
+        stratify_by: Option<&Dimension>,
+        rule: &SuppressionRule,
+    ) -> Vec<SuppressedStratum> {
+        let strata = self.get_breakdown(breakdown_type, stratify_by);
+        let columns = strata
+            .first()
+            .map(|stratum| stratum.breakdown.len())
+            .unwrap_or_default()
+            + 1;
+
+        let mut counts = Vec::new();
+        for stratum in &strata {
+            counts.extend(stratum.breakdown.iter().map(|count| count.value));
+            counts.push(stratum.total);
+        }
+
+        let events_only = !matches!(
+            breakdown_type,
+            BreakdownType::WithComplications | BreakdownType::WithEvent
+        );
+        let row_width = if events_only { columns - 1 } else { columns };
+
+        let mut lines: Vec<Vec<usize>> = (0..strata.len())
+            .map(|row| (row * columns..row * columns + row_width).collect())
+            .collect();
+        lines.extend((0..columns).map(|column| {
+            (0..strata.len())
+                .map(|row| row * columns + column)
+                .collect()
+        }));
+
+        let mut cells = suppress_counts(&counts, &lines, rule).into_iter();
+
+        strata
+            .into_iter()
+            .map(|stratum| {
+                let breakdown = stratum
+                    .breakdown
+                    .into_iter()
+                    .map(|count| LabeledCell {
+                        label: count.label,
+                        value: cells.next().unwrap(),
+                    })
+                    .collect();
+
+                SuppressedStratum {
+                    label: stratum.label,
+                    total: cells.next().unwrap(),
+                    breakdown,
+                }
+            })
+            .collect()
+    }
+
+    /// Suppresses rates within each stratum, and stratum sizes across them.
+    pub fn suppressed_stratified_breakdown_rates(
+        &self,
+        breakdown_type: BreakdownType,
+        stratify_by: Option<&Dimension>,
+        outcome: &Outcome,
+        rule: &SuppressionRule,
+    ) -> Vec<SuppressedStratum<SuppressedRate>> {
+        let strata = self.get_stratified_breakdown_rates(breakdown_type, stratify_by, outcome);
+        let totals: Vec<usize> = strata.iter().map(|stratum| stratum.total).collect();
+        let line: Vec<usize> = (0..totals.len()).collect();
+
+        strata
+            .into_iter()
+            .zip(suppress_counts(&totals, &[line], rule))
+            .map(|(stratum, total)| SuppressedStratum {
+                label: stratum.label,
+                total,
+                breakdown: suppress_rates(stratum.breakdown, rule),
+            })
+            .collect()
+    }
+
+    /// Suppresses a timeseries by the number of matching records in each
+    /// period. Percentages and rates are withheld wherever their numerator
+    /// would be, since the numerator can be recovered from them.
+    pub fn suppressed_timeseries(
+        &self,
+        timeseries_type: TimeseriesType,
+        period: Period,
+        rule: &SuppressionRule,
+    ) -> SuppressedTimeseries {
+        self.suppress_timeseries(
+            self.timeseries(timeseries_type, period),
+            timeseries_type,
+            period,
+            rule,
+        )
+    }
+
+    /// Suppresses an already computed timeseries of this view, such as one
+    /// over external denominators.
+    pub fn suppress_timeseries(
+        &self,
+        timeseries: Timeseries,
+        timeseries_type: TimeseriesType,
+        period: Period,
+        rule: &SuppressionRule,
+    ) -> SuppressedTimeseries {
+        // Periods are the line here, since a total over the range is
+        // usually published alongside
+        let suppress_series = |values: &[usize]| {
+            let line: Vec<usize> = (0..values.len()).collect();
+            suppress_counts(values, &[line], rule)
+        };
+
+        let numerators = |filter: fn(&&&AdverseEventRecord) -> bool| -> HashMap<NaiveDate, usize> {
+            self.period_counts(period, filter)
+                .into_iter()
+                .map(|dpc| (dpc.start, dpc.value))
+                .collect()
+        };
+
+        let percentages = |percentages: Vec<DatePeriodPercentage>,
+                           filter: fn(&&&AdverseEventRecord) -> bool| {
+            let numerators = numerators(filter);
+            let values: Vec<usize> = percentages
+                .iter()
+                .map(|dpp| numerators.get(&dpp.start).copied().unwrap_or_default())
+                .collect();
+
+            SuppressedTimeseries::Rates(
+                percentages
+                    .into_iter()
+                    .zip(suppress_series(&values))
+                    .map(|(dpp, cell)| DatePeriodContainer {
+                        period: dpp.period,
+                        start: dpp.start,
+                        end: dpp.end,
+                        value: cell.map(|_| dpp.value),
+                    })
+                    .collect(),
+            )
+        };
+
+        let filter: fn(&&&AdverseEventRecord) -> bool = match timeseries_type {
+            TimeseriesType::ComplicationSpecifiedPercentage => {
+                |record| record.complications.is_some()
+            }
+            TimeseriesType::ComplicationOccurredPercentage => {
+                |record| record.complications == Some(true)
+            }
+            _ => |record| !record.adverse_events.is_empty(),
+        };
+
+        match timeseries {
+            Timeseries::Counts(counts) => {
+                let values: Vec<usize> = counts.iter().map(|dpc| dpc.value).collect();
+                SuppressedTimeseries::Counts(
+                    counts
+                        .into_iter()
+                        .zip(suppress_series(&values))
+                        .map(|(dpc, cell)| DatePeriodContainer {
+                            period: dpc.period,
+                            start: dpc.start,
+                            end: dpc.end,
+                            value: cell,
+                        })
+                        .collect(),
+                )
+            }
+            Timeseries::Percentages(values) => percentages(values, filter),
+            Timeseries::ExposureRates(rates) => {
+                let values: Vec<usize> = rates.iter().map(|dpe| dpe.value.events).collect();
+                SuppressedTimeseries::Rates(
+                    rates
+                        .into_iter()
+                        .zip(suppress_series(&values))
+                        .map(|(dpe, cell)| DatePeriodContainer {
+                            period: dpe.period,
+                            start: dpe.start,
+                            end: dpe.end,
+                            value: cell.map(|_| dpe.value.rate),
+                        })
+                        .collect(),
+                )
+            }
+            Timeseries::Hours(hours) => SuppressedTimeseries::Hours(hours),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    fn small() -> Suppressible<usize> {
+        Suppressible::Suppressed(SuppressionReason::SmallCell(11))
+    }
+
+    fn complementary() -> Suppressible<usize> {
+        Suppressible::Suppressed(SuppressionReason::Complementary)
+    }
+
+    #[test]
+    fn suppress_counts_hides_a_complement() {
+        let rule = SuppressionRule::default();
+        let cells = suppress_counts(&[3, 40, 25, 0], &[vec![0, 1, 2, 3]], &rule);
+
+        assert_eq!(
+            cells,
+            [
+                small(),
+                Suppressible::Shown(40),
+                complementary(),
+                Suppressible::Shown(0)
+            ]
+        );
+        assert_eq!(
+            suppress_counts(&[40, 25], &[vec![0, 1]], &rule),
+            [Suppressible::Shown(40), Suppressible::Shown(25)]
+        );
+    }
+
+    #[test]
+    fn suppress_counts_can_suppress_zero() {
+        let rule = SuppressionRule {
+            threshold: 5,
+            suppress_zero: true,
+        };
+        let cells = suppress_counts(&[0, 9, 12], &[], &rule);
+
+        assert!(cells[0].is_suppressed());
+        assert!(!cells[1].is_suppressed() && !cells[2].is_suppressed());
+    }
+
+    #[test]
+    fn suppress_with_total_treats_the_total_as_a_cell() {
+        let rule = SuppressionRule::default();
+
+        // A lone small count is its own total
+        assert_eq!(suppress_with_total(&[3], &rule), (vec![small()], small()));
+        assert_eq!(
+            suppress_with_total(&[3, 20, 30], &rule),
+            (
+                vec![small(), complementary(), Suppressible::Shown(30)],
+                Suppressible::Shown(53)
+            )
+        );
+    }
+
+    #[test]
+    fn suppress_labeled_counts_takes_the_total_over_a_zero() {
+        let rule = SuppressionRule::default();
+        let counts = |values: &[usize]| {
+            values
+                .iter()
+                .map(|&value| LabeledCount {
+                    label: value.to_string(),
+                    value,
+                })
+                .collect()
+        };
+
+        let cells = |values| -> Vec<_> {
+            suppress_labeled_counts(counts(values), &rule)
+                .into_iter()
+                .map(|cell| cell.value)
+                .collect()
+        };
+
+        assert_eq!(
+            cells(&[12, 0, 3]),
+            [complementary(), Suppressible::Shown(0), small()]
+        );
+        // The small count is the total, which is withheld rather than the zero
+        assert_eq!(cells(&[0, 3]), [Suppressible::Shown(0), small()]);
+    }
+
+    #[test]
+    fn suppress_rates_hides_small_counts_without_the_outcome() {
+        let rule = SuppressionRule::default();
+        let rates = vec![
+            LabeledRate::from_counts("A".to_string(), 50, 47),
+            LabeledRate::from_counts("B".to_string(), 60, 30),
+            LabeledRate::from_counts("C".to_string(), 70, 35),
+        ];
+
+        let suppressed = suppress_rates(rates, &rule);
+        // A's 3 cases without the outcome follow from its total and count
+        assert!(suppressed[0].rate.is_suppressed());
+        assert!(suppressed[0].with_outcome.is_suppressed() || suppressed[0].total.is_suppressed());
+        // and another rate is withheld so the overall count doesn't give it away
+        assert_eq!(
+            suppressed
+                .iter()
+                .filter(|rate| rate.rate.is_suppressed())
+                .count(),
+            2
+        );
+        assert!(suppressed
+            .iter()
+            .all(|rate| rate.ci.is_suppressed() == rate.rate.is_suppressed()));
+    }
+
+    #[test]
+    fn suppressible_serializes_its_marker() {
+        let cells = vec![Suppressible::Shown(12), small(), complementary()];
+
+        assert_eq!(
+            serde_json::to_string(&cells).unwrap(),
+            r#"[12,"<11","suppressed"]"#
+        );
+    }
+
+    #[test]
+    fn stratified_patient_breakdown_leaves_totals_out_of_rows() {
+        // (location, smoker, with an event, records)
+        let groups = [
+            ("A", true, true, 8),
+            ("A", false, true, 2),
+            ("A", false, false, 10),
+            ("B", true, true, 15),
+            ("B", false, true, 12),
+        ];
+        let adverse_events = test_data::adverse_events(groups.iter().flat_map(
+            |&(location, smoker, event, records)| {
+                (0..records).map(move |_| TestRecord {
+                    location,
+                    smoker,
+                    ..TestRecord::with_event(event)
+                })
+            },
+        ));
+
+        let strata = adverse_events.view().suppressed_stratified_breakdown(
+            BreakdownType::PatientSmoker,
+            Some(&Dimension::Location),
+            &SuppressionRule::new(5),
+        );
+
+        // Stratum sizes count records without events too, so they don't
+        // reveal the suppressed categories
+        let totals: Vec<_> = strata.iter().map(|stratum| stratum.total).collect();
+        assert_eq!(totals, [Suppressible::Shown(20), Suppressible::Shown(27)]);
+        for stratum in &strata {
+            let suppressed = stratum
+                .breakdown
+                .iter()
+                .filter(|cell| cell.value.is_suppressed())
+                .count();
+            assert_ne!(suppressed, 1);
+        }
+        assert!(strata[0]
+            .breakdown
+            .iter()
+            .all(|cell| cell.value.is_suppressed()));
+    }
+}
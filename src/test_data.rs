@@ -0,0 +1,105 @@
+use super::*;
+
+/// A row of an extract for tests, with plausible values for anything a test
+/// doesn't care about.
+#[derive(Debug, Clone)]
+pub struct TestRecord {
+    pub date: &'static str,
+    // Numbered by position when not given
+    pub mrn: Option<String>,
+    pub episode_id: Option<String>,
+    pub patient_name: &'static str,
+    pub diagnosis: &'static str,
+    pub anesthesiologist: &'static str,
+    // One name per line, as in the extract
+    pub anesthesia_staff: &'static str,
+    pub location: &'static str,
+    // Comma separated, as in the extract
+    pub adverse_events: &'static str,
+    pub asa: u8,
+    pub an_start: &'static str,
+    pub an_stop: &'static str,
+    pub smoker: bool,
+    pub age: u8,
+    pub bmi: f64,
+    // Columns after the record's own, as (header, value)
+    pub extra: Vec<(&'static str, &'static str)>,
+}
+
+impl Default for TestRecord {
+    fn default() -> Self {
+        TestRecord {
+            date: "01/01/19",
+            mrn: None,
+            episode_id: None,
+            patient_name: "Name",
+            diagnosis: "dx",
+            anesthesiologist: "Dr A",
+            anesthesia_staff: "",
+            location: "OR",
+            adverse_events: "",
+            asa: 2,
+            an_start: "0800",
+            an_stop: "0900",
+            smoker: false,
+            age: 50,
+            bmi: 25.0,
+            extra: Vec::new(),
+        }
+    }
+}
+
+impl TestRecord {
+    pub fn with_event(event: bool) -> Self {
+        TestRecord {
+            adverse_events: if event { "nausea" } else { "" },
+            ..Default::default()
+        }
+    }
+}
+
+/// Writes records as an extract, taking extra headers from the first.
+pub fn csv(records: &[TestRecord]) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let extra_headers: Vec<&str> = records
+        .first()
+        .map(|record| record.extra.iter().map(|(header, _)| *header).collect())
+        .unwrap_or_default();
+    writer
+        .write_record(RECORD_COLUMNS.iter().chain(&extra_headers))
+        .unwrap();
+
+    for (i, record) in records.iter().enumerate() {
+        let mut row = vec![
+            record.date.to_string(),
+            record.mrn.clone().unwrap_or_else(|| format!("M{}", i)),
+            record
+                .episode_id
+                .clone()
+                .unwrap_or_else(|| format!("E{}", i)),
+            record.patient_name.to_string(),
+            record.diagnosis.to_string(),
+            "proc".to_string(),
+            record.anesthesiologist.to_string(),
+            record.anesthesia_staff.to_string(),
+            record.location.to_string(),
+            "No".to_string(),
+            record.adverse_events.to_string(),
+            record.asa.to_string(),
+            record.an_start.to_string(),
+            record.an_stop.to_string(),
+            if record.smoker { "1" } else { "0" }.to_string(),
+            record.age.to_string(),
+            record.bmi.to_string(),
+        ];
+        row.extend(record.extra.iter().map(|(_, value)| value.to_string()));
+        writer.write_record(&row).unwrap();
+    }
+
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+pub fn adverse_events(records: impl IntoIterator<Item = TestRecord>) -> AdverseEvents {
+    let records: Vec<TestRecord> = records.into_iter().collect();
+    AdverseEvents::from_csv_reader(csv(&records).as_bytes()).unwrap()
+}
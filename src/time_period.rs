@@ -26,7 +26,7 @@ impl<'a> DatePeriodView<'a> {
     }
 }
 
-#[derive(Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 #[serde(rename_all(serialize = "camelCase"))]
 pub enum Period {
     Day,
@@ -52,6 +52,7 @@ impl FromStr for Period {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TimeseriesType {
     EventCount,
     EventPercentage,
@@ -59,6 +60,8 @@ pub enum TimeseriesType {
     ComplicationSpecifiedPercentage,
     ComplicationOccurredCount,
     ComplicationOccurredPercentage,
+    AnesthesiaHours,
+    EventsPerHundredHours,
 }
 
 impl FromStr for TimeseriesType {
@@ -74,6 +77,8 @@ impl FromStr for TimeseriesType {
             }
             "complicationOccurred" => Ok(TimeseriesType::ComplicationOccurredCount),
             "complicationOccurredPercentage" => Ok(TimeseriesType::ComplicationOccurredPercentage),
+            "anesthesiaHours" => Ok(TimeseriesType::AnesthesiaHours),
+            "eventsPer100Hours" => Ok(TimeseriesType::EventsPerHundredHours),
             x => Err(crate::Error::ParseError {
                 type_name: "TimeseriesType",
                 received: x.to_string(),
@@ -81,3 +86,103 @@ impl FromStr for TimeseriesType {
         }
     }
 }
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Timeseries {
+    Counts(Vec<DatePeriodCount>),
+    Percentages(Vec<DatePeriodPercentage>),
+    Hours(Vec<DatePeriodHours>),
+    ExposureRates(Vec<DatePeriodExposure>),
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn timeseries(&self, timeseries_type: TimeseriesType, period: Period) -> Timeseries {
+        match timeseries_type {
+            TimeseriesType::EventCount => Timeseries::Counts(
+                self.period_counts(period, |record| !record.adverse_events.is_empty()),
+            ),
+            TimeseriesType::EventPercentage => Timeseries::Percentages(
+                self.period_percentages(period, |record| !record.adverse_events.is_empty()),
+            ),
+            TimeseriesType::ComplicationSpecifiedCount => Timeseries::Counts(
+                self.period_counts(period, |record| record.complications.is_some()),
+            ),
+            TimeseriesType::ComplicationSpecifiedPercentage => Timeseries::Percentages(
+                self.period_percentages(period, |record| record.complications.is_some()),
+            ),
+            TimeseriesType::ComplicationOccurredCount => Timeseries::Counts(
+                self.period_counts(period, |record| record.complications == Some(true)),
+            ),
+            TimeseriesType::ComplicationOccurredPercentage => Timeseries::Percentages(
+                self.period_percentages(period, |record| record.complications == Some(true)),
+            ),
+            TimeseriesType::AnesthesiaHours => Timeseries::Hours(
+                self.by_period(period, |_| true)
+                    .into_iter()
+                    .map(|dpv| DatePeriodHours {
+                        period: dpv.period,
+                        start: dpv.start,
+                        end: dpv.end,
+                        value: dpv.value.anesthesia_hours(),
+                    })
+                    .collect(),
+            ),
+            TimeseriesType::EventsPerHundredHours => Timeseries::ExposureRates(
+                self.by_period(period, |_| true)
+                    .into_iter()
+                    .map(|dpv| DatePeriodExposure {
+                        period: dpv.period,
+                        start: dpv.start,
+                        end: dpv.end,
+                        value: dpv.value.exposure_rate(),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn period_counts<F>(&self, period: Period, filter: F) -> Vec<DatePeriodCount>
+    where
+        F: FnMut(&&&AdverseEventRecord) -> bool,
+    {
+        self.by_period(period, filter)
+            .into_iter()
+            .map(|dpv| dpv.to_count())
+            .collect()
+    }
+
+    pub fn period_percentages<F>(&self, period: Period, filter: F) -> Vec<DatePeriodPercentage>
+    where
+        F: FnMut(&&&AdverseEventRecord) -> bool,
+    {
+        let mut matching: HashMap<(NaiveDate, NaiveDate), DatePeriodView> = self
+            .by_period(period, filter)
+            .into_iter()
+            .map(|dpv| ((dpv.start, dpv.end), dpv))
+            .collect();
+
+        self.by_period(period, |_| true)
+            .into_iter()
+            .map(|dpv| {
+                let matching_count: usize = matching
+                    .remove(&(dpv.start, dpv.end))
+                    .map(|dpv| dpv.value.records.len())
+                    .unwrap_or_default();
+
+                let total_count = dpv.value.records.len();
+
+                DatePeriodPercentage {
+                    period: dpv.period,
+                    start: dpv.start,
+                    end: dpv.end,
+                    value: if total_count == 0 {
+                        0.0
+                    } else {
+                        matching_count as f64 / total_count as f64 * 100.0
+                    },
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,445 @@
+use super::*;
+
+use serde::{ser::Error as _, Serializer};
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum RecordField {
+    Date,
+    Mrn,
+    EpisodeId,
+    PatientName,
+    Diagnosis,
+    Procedure,
+    Anesthesiologist,
+    AnesthesiaStaff,
+    Location,
+    Complications,
+    AdverseEvents,
+    Asa,
+    AnStart,
+    AnStop,
+    Smoker,
+    Age,
+    Bmi,
+    Attributes,
+}
+
+impl RecordField {
+    pub const ALL: [RecordField; 18] = [
+        RecordField::Date,
+        RecordField::Mrn,
+        RecordField::EpisodeId,
+        RecordField::PatientName,
+        RecordField::Diagnosis,
+        RecordField::Procedure,
+        RecordField::Anesthesiologist,
+        RecordField::AnesthesiaStaff,
+        RecordField::Location,
+        RecordField::Complications,
+        RecordField::AdverseEvents,
+        RecordField::Asa,
+        RecordField::AnStart,
+        RecordField::AnStop,
+        RecordField::Smoker,
+        RecordField::Age,
+        RecordField::Bmi,
+        RecordField::Attributes,
+    ];
+
+    /// Key the field is serialized under.
+    pub fn key(&self) -> &'static str {
+        match self {
+            RecordField::Date => "date",
+            RecordField::Mrn => "mrn",
+            RecordField::EpisodeId => "episodeId",
+            RecordField::PatientName => "patientName",
+            RecordField::Diagnosis => "diagnosis",
+            RecordField::Procedure => "procedure",
+            RecordField::Anesthesiologist => "anesthesiologist",
+            RecordField::AnesthesiaStaff => "anesthesiaStaff",
+            RecordField::Location => "location",
+            RecordField::Complications => "complications",
+            RecordField::AdverseEvents => "adverseEvents",
+            RecordField::Asa => "asa",
+            RecordField::AnStart => "anStart",
+            RecordField::AnStop => "anStop",
+            RecordField::Smoker => "smoker",
+            RecordField::Age => "age",
+            RecordField::Bmi => "bmi",
+            RecordField::Attributes => "attributes",
+        }
+    }
+
+    /// Column the field is read from and exported to, `None` for
+    /// attributes, which each get their own column.
+    pub fn column(&self) -> Option<&'static str> {
+        match self {
+            RecordField::Date => Some("Date"),
+            RecordField::Mrn => Some("MRN"),
+            RecordField::EpisodeId => Some("Episode ID"),
+            RecordField::PatientName => Some("Patient Name"),
+            RecordField::Diagnosis => Some("Diagnosis"),
+            RecordField::Procedure => Some("Procedure"),
+            RecordField::Anesthesiologist => Some("Anesthesiologist"),
+            RecordField::AnesthesiaStaff => Some("Anesthesia Staff"),
+            RecordField::Location => Some("Location"),
+            RecordField::Complications => Some("Anesthesia Complications"),
+            RecordField::AdverseEvents => Some("Adverse Events"),
+            RecordField::Asa => Some("ASA"),
+            RecordField::AnStart => Some("An Start"),
+            RecordField::AnStop => Some("An Stop"),
+            RecordField::Smoker => Some("Smoker?"),
+            RecordField::Age => Some("Age (Years)"),
+            RecordField::Bmi => Some("BMI"),
+            RecordField::Attributes => None,
+        }
+    }
+}
+
+impl FromStr for RecordField {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RecordField::ALL
+            .iter()
+            .copied()
+            .find(|field| field.key() == s)
+            .ok_or_else(|| crate::Error::ParseError {
+                type_name: "RecordField",
+                received: s.to_string(),
+            })
+    }
+}
+
+/// Who is looking at the data, and so which record fields they may see or
+/// filter on. Ordered from least to most access.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum VisibilityProfile {
+    // Summaries only; no record-level output, and no filtering on
+    // identifiers
+    AggregateOnly,
+    // Records without direct patient identifiers
+    QiReviewer,
+    #[default]
+    FullPhi,
+}
+
+impl FromStr for VisibilityProfile {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "aggregateOnly" => Ok(VisibilityProfile::AggregateOnly),
+            "qiReviewer" => Ok(VisibilityProfile::QiReviewer),
+            "fullPhi" => Ok(VisibilityProfile::FullPhi),
+            x => Err(crate::Error::ParseError {
+                type_name: "VisibilityProfile",
+                received: x.to_string(),
+            }),
+        }
+    }
+}
+
+impl VisibilityProfile {
+    /// Whether the field may be emitted in record-level output.
+    pub fn can_view(&self, field: RecordField) -> bool {
+        match self {
+            VisibilityProfile::AggregateOnly => false,
+            VisibilityProfile::QiReviewer => !is_identifying(field),
+            VisibilityProfile::FullPhi => true,
+        }
+    }
+
+    /// Whether views may be filtered or looked up by the field.
+    pub fn can_query(&self, field: RecordField) -> bool {
+        match self {
+            VisibilityProfile::AggregateOnly | VisibilityProfile::QiReviewer => {
+                !is_identifying(field)
+            }
+            VisibilityProfile::FullPhi => true,
+        }
+    }
+
+    /// Whether an attribute may be viewed, wherever records can be, and
+    /// filtered or grouped on. Attributes can hold anything, including
+    /// identifiers from extra columns, so narrower profiles only get the
+    /// ones explicitly allowed.
+    pub fn can_use_attribute(&self, name: &str, allowed: &BTreeSet<String>) -> bool {
+        *self == VisibilityProfile::FullPhi || allowed.contains(name)
+    }
+
+    pub fn allows_records(&self) -> bool {
+        RecordField::ALL.iter().any(|field| self.can_view(*field))
+    }
+
+    pub fn visible_fields(&self) -> Vec<RecordField> {
+        RecordField::ALL
+            .iter()
+            .copied()
+            .filter(|field| self.can_view(*field))
+            .collect()
+    }
+
+    pub fn check_view(&self, field: RecordField) -> Result<(), Error> {
+        if self.can_view(field) {
+            Ok(())
+        } else {
+            Err(Error::FieldNotVisible(field))
+        }
+    }
+
+    pub fn check_query(&self, field: RecordField) -> Result<(), Error> {
+        if self.can_query(field) {
+            Ok(())
+        } else {
+            Err(Error::FieldNotVisible(field))
+        }
+    }
+
+    pub fn check_attribute(&self, name: &str, allowed: &BTreeSet<String>) -> Result<(), Error> {
+        if self.can_use_attribute(name, allowed) {
+            Ok(())
+        } else {
+            Err(Error::FieldNotVisible(RecordField::Attributes))
+        }
+    }
+
+    /// Checks that views may be grouped by the dimension, whose values
+    /// become the group labels.
+    pub fn check_dimension(
+        &self,
+        dimension: &Dimension,
+        allowed: &BTreeSet<String>,
+    ) -> Result<(), Error> {
+        match dimension {
+            Dimension::Anesthesiologist => self.check_query(RecordField::Anesthesiologist),
+            Dimension::Location => self.check_query(RecordField::Location),
+            Dimension::Procedure => self.check_query(RecordField::Procedure),
+            Dimension::Diagnosis => self.check_query(RecordField::Diagnosis),
+            Dimension::Asa => self.check_query(RecordField::Asa),
+            Dimension::Smoker => self.check_query(RecordField::Smoker),
+            Dimension::StaffCount => self.check_query(RecordField::AnesthesiaStaff),
+            Dimension::ConcurrentCases | Dimension::FacilityConcurrentCases => self
+                .check_query(RecordField::AnStart)
+                .and_then(|_| self.check_query(RecordField::AnStop)),
+            // Prior events are found by linking cases on MRN
+            Dimension::PriorEvent => self.check_query(RecordField::Mrn),
+            Dimension::Attribute(name) => self.check_attribute(name, allowed),
+            Dimension::Period(_) => self.check_query(RecordField::Date),
+            Dimension::Binned(field, _) => self.check_numeric_field(field, allowed),
+        }
+    }
+
+    /// Checks that rates of the outcome may be reported, since an attribute
+    /// outcome reveals the attribute's values.
+    pub fn check_outcome(
+        &self,
+        outcome: &Outcome,
+        allowed: &BTreeSet<String>,
+    ) -> Result<(), Error> {
+        match outcome {
+            Outcome::AnyEvent | Outcome::Event(_) => self.check_query(RecordField::AdverseEvents),
+            Outcome::ComplicationOccurred => self.check_query(RecordField::Complications),
+            Outcome::Attribute(name) => self.check_attribute(name, allowed),
+        }
+    }
+
+    pub fn check_numeric_field(
+        &self,
+        field: &NumericField,
+        allowed: &BTreeSet<String>,
+    ) -> Result<(), Error> {
+        match field {
+            NumericField::Age => self.check_query(RecordField::Age),
+            NumericField::Bmi => self.check_query(RecordField::Bmi),
+            NumericField::Asa => self.check_query(RecordField::Asa),
+            NumericField::DurationHours
+            | NumericField::ConcurrentCases
+            | NumericField::FacilityConcurrentCases => self
+                .check_query(RecordField::AnStart)
+                .and_then(|_| self.check_query(RecordField::AnStop)),
+            NumericField::Attribute(name) => self.check_attribute(name, allowed),
+        }
+    }
+
+    pub fn check_records(&self) -> Result<(), Error> {
+        if self.allows_records() {
+            Ok(())
+        } else {
+            Err(Error::InsufficientData(
+                "this visibility profile only allows aggregate output",
+            ))
+        }
+    }
+}
+
+/// Direct identifiers, and attributes as a whole since they may hold them.
+fn is_identifying(field: RecordField) -> bool {
+    matches!(
+        field,
+        RecordField::Mrn
+            | RecordField::EpisodeId
+            | RecordField::PatientName
+            | RecordField::Attributes
+    )
+}
+
+/// A record serialized with only the fields and attributes its profile can
+/// view.
+pub struct RedactedRecord<'a> {
+    pub record: &'a AdverseEventRecord,
+    pub profile: VisibilityProfile,
+    pub allowed_attributes: &'a BTreeSet<String>,
+}
+
+impl<'a> Serialize for RedactedRecord<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // Going through a value keeps the same field formats as serializing
+        // the record directly
+        let mut value = serde_json::to_value(self.record).map_err(S::Error::custom)?;
+        if let Some(fields) = value.as_object_mut() {
+            for field in RecordField::ALL.iter() {
+                if !self.profile.can_view(*field) {
+                    fields.remove(field.key());
+                }
+            }
+
+            // Attributes that can't be viewed as a whole may still be
+            // allowed one by one
+            if !self.profile.can_view(RecordField::Attributes) {
+                let attributes: serde_json::Map<String, serde_json::Value> = self
+                    .record
+                    .attributes
+                    .iter()
+                    .filter(|(name, _)| self.allowed_attributes.contains(*name))
+                    .map(|(name, value)| Ok((name.clone(), serde_json::to_value(value)?)))
+                    .collect::<Result<_, serde_json::Error>>()
+                    .map_err(S::Error::custom)?;
+                if !attributes.is_empty() {
+                    fields.insert(
+                        RecordField::Attributes.key().to_string(),
+                        serde_json::Value::Object(attributes),
+                    );
+                }
+            }
+        }
+
+        value.serialize(serializer)
+    }
+}
+
+impl<'a> AdverseEventsView<'a> {
+    pub fn redacted_records<'b>(
+        &self,
+        profile: VisibilityProfile,
+        allowed_attributes: &'b BTreeSet<String>,
+    ) -> Result<Vec<RedactedRecord<'b>>, Error>
+    where
+        'a: 'b,
+    {
+        profile.check_records()?;
+
+        Ok(self
+            .records
+            .iter()
+            .map(|record| RedactedRecord {
+                record,
+                profile,
+                allowed_attributes,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{self, TestRecord};
+
+    #[test]
+    fn hidden_attribute_outcomes_are_refused() {
+        let allowed: BTreeSet<String> = std::iter::once("Case Class".to_string()).collect();
+        let hidden = Outcome::Attribute("Readmitted".to_string());
+        let listed = Outcome::Attribute("Case Class".to_string());
+
+        for profile in [
+            VisibilityProfile::AggregateOnly,
+            VisibilityProfile::QiReviewer,
+        ] {
+            assert!(matches!(
+                profile.check_outcome(&hidden, &allowed),
+                Err(Error::FieldNotVisible(RecordField::Attributes))
+            ));
+            assert!(profile.check_outcome(&listed, &allowed).is_ok());
+            assert!(profile.check_outcome(&Outcome::AnyEvent, &allowed).is_ok());
+        }
+        assert!(VisibilityProfile::FullPhi
+            .check_outcome(&hidden, &BTreeSet::new())
+            .is_ok());
+    }
+
+    #[test]
+    fn redacted_records_drop_identifiers_and_unlisted_attributes() {
+        let adverse_events = test_data::adverse_events(vec![TestRecord {
+            extra: vec![("Case Class", "Elective"), ("Home Phone", "555-0100")],
+            ..Default::default()
+        }]);
+        let view = adverse_events.view();
+        let allowed: BTreeSet<String> = std::iter::once("Case Class".to_string()).collect();
+        let serialized = |profile| {
+            let records = view.redacted_records(profile, &allowed).unwrap();
+            serde_json::to_value(&records[0]).unwrap()
+        };
+
+        let redacted = serialized(VisibilityProfile::QiReviewer);
+        for key in ["mrn", "episodeId", "patientName"] {
+            assert!(redacted.get(key).is_none());
+        }
+        assert_eq!(redacted["location"], "OR");
+        assert_eq!(
+            redacted["attributes"],
+            serde_json::json!({"Case Class": "Elective"})
+        );
+
+        let full = serialized(VisibilityProfile::FullPhi);
+        assert_eq!(
+            full,
+            serde_json::to_value(&adverse_events.records[0]).unwrap()
+        );
+
+        assert!(view
+            .redacted_records(VisibilityProfile::AggregateOnly, &allowed)
+            .is_err());
+    }
+
+    #[test]
+    fn narrower_profiles_refuse_identifying_queries() {
+        let allowed = BTreeSet::new();
+        let profile: VisibilityProfile = "qiReviewer".parse().unwrap();
+
+        assert!(profile.check_query(RecordField::Location).is_ok());
+        assert!(profile.check_query(RecordField::Mrn).is_err());
+        assert!(profile
+            .check_dimension(&Dimension::PriorEvent, &allowed)
+            .is_err());
+        assert!(profile
+            .check_dimension(&Dimension::Attribute("Home Phone".to_string()), &allowed)
+            .is_err());
+        assert!(VisibilityProfile::AggregateOnly
+            .check_dimension(&Dimension::Location, &allowed)
+            .is_ok());
+        assert!(!VisibilityProfile::AggregateOnly.allows_records());
+        assert_eq!(
+            "episodeId".parse::<RecordField>().ok(),
+            Some(RecordField::EpisodeId)
+        );
+        assert!("fullphi".parse::<VisibilityProfile>().is_err());
+    }
+}
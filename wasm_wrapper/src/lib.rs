@@ -4,15 +4,18 @@ use serde_json;
 use wasm_bindgen::prelude::*;
 
 use adverse_events::{
-    sort_map, AdverseEventRecord, AdverseEvents, AdverseEventsView, BreakdownType,
-    DatePeriodPercentage, DatePeriodView, Error as AdverseEventsError, Period, TimeseriesType,
+    compare as compare_views, detect_format, encrypt, sort_map, suppress_rates, AdverseEvents,
+    AdverseEventsView, Binning, BreakdownType, Denominated, DenominatorFilter, Denominators,
+    Dimension, Error as AdverseEventsError, LabeledCount, LookupField, LookupTable, NumericField,
+    Outcome, Period, RecordField, ReferencePopulation, RepeatMatch, StaffAttribution,
+    SupplementConfig, SuppressionRule, TimeseriesType, VisibilityProfile,
 };
 
 use std::{
     cell::{Cell, UnsafeCell},
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     convert::From,
-    io::{BufReader, Cursor, Seek, SeekFrom},
+    io::Cursor,
     str::FromStr,
     sync::Mutex,
 };
@@ -27,23 +30,103 @@ lazy_static! {
     static ref VIEW_MAP: Mutex<UnsafeCell<HashMap<ViewHandle, AdverseEventsView<'static>>>> =
         Mutex::new(UnsafeCell::new(HashMap::new()));
     static ref NEXT_HANDLE: Mutex<Cell<ViewHandle>> = Mutex::new(Cell::new(0));
+    static ref PROFILE: Mutex<Cell<VisibilityProfile>> =
+        Mutex::new(Cell::new(VisibilityProfile::default()));
+    static ref VISIBLE_ATTRIBUTES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+    // Date windows views were narrowed to, for limiting denominators; views
+    // without one cover every date
+    static ref VIEW_FILTERS: Mutex<HashMap<ViewHandle, DenominatorFilter>> =
+        Mutex::new(HashMap::new());
 }
 
+fn profile() -> Result<VisibilityProfile, JsValue> {
+    Ok(PROFILE
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire visibility profile"))?
+        .get())
+}
+
+/// Restricts which record fields later calls return. The profile can only
+/// be narrowed for the rest of the session, never widened.
+///
+/// This guards against showing identifiers by accident; the data itself is
+/// still in the browser, so it is not a substitute for controlling who
+/// receives the file.
 #[wasm_bindgen]
-pub fn get_events(data: &[u8]) -> Result<ViewHandle, JsValue> {
-    let cursor = Cursor::new(data);
-    let mut buf = BufReader::new(cursor);
-    let adverse_events = AdverseEvents::from_zip(&mut buf)
-        .or_else(|_| {
-            buf.seek(SeekFrom::Start(0))?;
-            AdverseEvents::from_csv_reader(&mut buf)
-        })
-        .map_err(|e| JsValue::from_str(&format!("{:?}", e)))?;
+pub fn set_visibility_profile(profile: &str) -> Result<(), JsValue> {
+    let profile = VisibilityProfile::from_str(profile)
+        .map_err(|_| JsValue::from_str("invalid visibility profile"))?;
+
+    let current = PROFILE
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire visibility profile"))?;
+    if profile > current.get() {
+        return Err(JsValue::from_str("visibility profile can only be narrowed"));
+    }
+    current.set(profile);
+
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn get_visibility_profile() -> Result<String, JsValue> {
+    serde_json::to_string(&profile()?)
+        .map_err(|_| JsValue::from_str("failed serializing visibility profile"))
+}
+
+fn view_filter(handle: ViewHandle) -> Result<DenominatorFilter, JsValue> {
+    Ok(VIEW_FILTERS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire view filters"))?
+        .get(&handle)
+        .cloned()
+        .unwrap_or_default())
+}
+
+fn visible_attributes() -> Result<BTreeSet<String>, JsValue> {
+    Ok(VISIBLE_ATTRIBUTES
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire visible attributes"))?
+        .clone())
+}
+
+/// Sets which attributes narrower profiles may see and filter on, given as
+/// a JSON array of names. Every other attribute is hidden from them. Once
+/// the profile is narrowed the list can only shrink.
+#[wasm_bindgen]
+pub fn set_visible_attributes(names: &str) -> Result<(), JsValue> {
+    let names: BTreeSet<String> =
+        serde_json::from_str(names).map_err(|_| JsValue::from_str("invalid attribute names"))?;
+
+    let profile = profile()?;
+    let mut current = VISIBLE_ATTRIBUTES
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire visible attributes"))?;
+    if profile != VisibilityProfile::FullPhi && !names.is_subset(&current) {
+        return Err(JsValue::from_str("visible attributes can only be narrowed"));
+    }
+    *current = names;
+
+    Ok(())
+}
+
+/// Loads records from an export in any format `detect_input_format`
+/// recognizes, or from data encrypted with `encrypt_data` or
+/// `get_encrypted_snapshot`. The password is used for whichever of those
+/// needs one.
+#[wasm_bindgen]
+pub fn get_events(data: &[u8], password: Option<String>) -> Result<ViewHandle, JsValue> {
+    let adverse_events = AdverseEvents::load(data, password.as_deref())
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
 
     let mut map_cell = VIEW_MAP
         .lock()
         .map_err(|_| JsValue::from_str("could not update views"))?;
     map_cell.get_mut().clear();
+    VIEW_FILTERS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not update view filters"))?
+        .clear();
     NEXT_HANDLE
         .lock()
         .map_err(|_| JsValue::from_str("could not acquire next handle"))?
@@ -66,6 +149,142 @@ pub fn get_events(data: &[u8]) -> Result<ViewHandle, JsValue> {
     Ok(0)
 }
 
+#[wasm_bindgen]
+pub fn detect_input_format(data: &[u8]) -> Result<String, JsValue> {
+    serde_json::to_string(&detect_format(data))
+        .map_err(|_| JsValue::from_str("failed serializing input format"))
+}
+
+/// Encrypts a dataset under a passphrase, for keeping in browser storage.
+#[wasm_bindgen]
+pub fn encrypt_data(data: &[u8], passphrase: &str) -> Result<Vec<u8>, JsValue> {
+    encrypt(data, passphrase).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Encrypts the loaded records, including any attributes attached since
+/// loading, so they can be restored with `get_events`. Only allowed while
+/// the visibility profile is `fullPhi`.
+#[wasm_bindgen]
+pub fn get_encrypted_snapshot(passphrase: &str) -> Result<Vec<u8>, JsValue> {
+    let records_cell = RECORDS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire records"))?;
+
+    unsafe { (*records_cell.get()).encrypted_snapshot(passphrase, profile()?) }
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Attaches lookup attributes to the loaded records. Existing views are
+/// released since the records they point to are modified, leaving only the
+/// base view.
+#[wasm_bindgen]
+pub fn apply_lookup(field: &str, data: &[u8]) -> Result<String, JsValue> {
+    let field =
+        LookupField::from_str(field).map_err(|_| JsValue::from_str("invalid lookup field"))?;
+    let table = LookupTable::from_csv_reader(field, Cursor::new(data))
+        .map_err(|e| JsValue::from_str(&format!("{:?}", e)))?;
+
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not update views"))?;
+    map_cell.get_mut().clear();
+    VIEW_FILTERS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not update view filters"))?
+        .clear();
+    NEXT_HANDLE
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire next handle"))?
+        .set(1);
+
+    let records_cell = RECORDS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not update records"))?;
+    let report = unsafe {
+        let records = records_cell.get();
+        let report = (*records).apply_lookup(&table);
+
+        let map = map_cell.get();
+        (*map).insert(0, (*records).view());
+
+        report
+    };
+
+    serde_json::to_string(&report)
+        .map_err(|_| JsValue::from_str("failed serializing lookup report"))
+}
+
+/// Joins a supplementary extract onto the loaded records, given its config
+/// as JSON. Like `apply_lookup`, this leaves only the base view. Its
+/// columns are hidden from narrower profiles unless made visible with
+/// `set_visible_attributes`.
+#[wasm_bindgen]
+pub fn join_supplement(config: &str, data: &[u8]) -> Result<String, JsValue> {
+    let config: SupplementConfig =
+        serde_json::from_str(config).map_err(|_| JsValue::from_str("invalid supplement config"))?;
+
+    // The report lists episode IDs that didn't match
+    profile()?
+        .check_view(RecordField::EpisodeId)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not update views"))?;
+    map_cell.get_mut().clear();
+    VIEW_FILTERS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not update view filters"))?
+        .clear();
+    NEXT_HANDLE
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire next handle"))?
+        .set(1);
+
+    let records_cell = RECORDS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not update records"))?;
+    let report = unsafe {
+        let records = records_cell.get();
+        let report = (*records).join_supplement(&config, Cursor::new(data));
+
+        let map = map_cell.get();
+        (*map).insert(0, (*records).view());
+
+        report
+    }
+    .map_err(|e| JsValue::from_str(&format!("{:?}", e)))?;
+
+    serde_json::to_string(&report).map_err(|_| JsValue::from_str("failed serializing join report"))
+}
+
+#[wasm_bindgen]
+pub fn with_attribute(handle: ViewHandle, name: &str, value: &str) -> Result<ViewHandle, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not aquire views"))?;
+
+    let map = map_cell.get_mut();
+    let view = map
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    profile()?
+        .check_attribute(name, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let new_view = view.with_attribute(name, value);
+    let mut next_handle_lock = NEXT_HANDLE
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire next handle"))?;
+    let next_handle = next_handle_lock.get_mut();
+    let handle: ViewHandle = *next_handle;
+    map.insert(handle, new_view);
+    *next_handle += 1;
+
+    Ok(handle)
+}
+
 #[wasm_bindgen]
 pub fn len(handle: ViewHandle) -> Result<u32, JsValue> {
     let mut map_cell = VIEW_MAP
@@ -80,7 +299,7 @@ pub fn len(handle: ViewHandle) -> Result<u32, JsValue> {
 }
 
 #[wasm_bindgen]
-pub fn event_counts(handle: ViewHandle) -> Result<String, JsValue> {
+pub fn event_counts(handle: ViewHandle, suppress_below: Option<usize>) -> Result<String, JsValue> {
     let mut map_cell = VIEW_MAP
         .lock()
         .map_err(|_| JsValue::from_str("could not acquire views"))?;
@@ -89,8 +308,15 @@ pub fn event_counts(handle: ViewHandle) -> Result<String, JsValue> {
         .get_mut()
         .get(&handle)
         .ok_or(JsValue::from_str("no view found for handle"))?;
-    let counts = sort_map(view.event_counts());
-    serde_json::to_string(&counts).map_err(|_| JsValue::from_str("failed serializing counts"))
+    match suppress_below {
+        Some(threshold) => serde_json::to_string(
+            &view
+                .suppressed_event_counts(&SuppressionRule::new(threshold))
+                .0,
+        ),
+        None => serde_json::to_string(&sort_map(view.event_counts())),
+    }
+    .map_err(|_| JsValue::from_str("failed serializing counts"))
 }
 
 #[wasm_bindgen]
@@ -104,6 +330,10 @@ pub fn with_complications_specified(handle: ViewHandle) -> Result<ViewHandle, Js
         .get(&handle)
         .ok_or(JsValue::from_str("no view found for handle"))?;
 
+    profile()?
+        .check_query(RecordField::Complications)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
     let new_view = view.with_filter(|r| r.complications.is_some());
     let mut next_handle_lock = NEXT_HANDLE
         .lock()
@@ -127,6 +357,10 @@ pub fn with_complications_occurred(handle: ViewHandle) -> Result<ViewHandle, JsV
         .get(&handle)
         .ok_or(JsValue::from_str("no view found for handle"))?;
 
+    profile()?
+        .check_query(RecordField::Complications)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
     let new_view = view.with_filter(|r| r.complications == Some(true));
     let mut next_handle_lock = NEXT_HANDLE
         .lock()
@@ -150,6 +384,10 @@ pub fn with_any_event(handle: ViewHandle) -> Result<ViewHandle, JsValue> {
         .get(&handle)
         .ok_or(JsValue::from_str("no view found for handle"))?;
 
+    profile()?
+        .check_query(RecordField::AdverseEvents)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
     let new_view = view.with_any_event();
     let mut next_handle_lock = NEXT_HANDLE
         .lock()
@@ -173,6 +411,10 @@ pub fn with_event(handle: ViewHandle, event: &str) -> Result<ViewHandle, JsValue
         .get(&handle)
         .ok_or(JsValue::from_str("no view found for handle"))?;
 
+    profile()?
+        .check_query(RecordField::AdverseEvents)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
     let new_view = view.with_event(event);
     let mut next_handle_lock = NEXT_HANDLE
         .lock()
@@ -196,18 +438,30 @@ pub fn between(handle: ViewHandle, start: &str, end: &str) -> Result<ViewHandle,
         .get(&handle)
         .ok_or(JsValue::from_str("no view found for handle"))?;
 
+    profile()?
+        .check_query(RecordField::Date)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
     let start = NaiveDate::parse_from_str(start, ISO_DATE_FORMAT)
         .map_err(|_| JsValue::from_str("failed parsing start date"))?;
     let end = NaiveDate::parse_from_str(end, ISO_DATE_FORMAT)
         .map_err(|_| JsValue::from_str("failed parsing end date"))?;
 
     let new_view = view.between(start, end);
+    let mut filter = view_filter(handle)?;
+    filter.from = Some(filter.from.map_or(start, |from| from.max(start)));
+    filter.to = Some(filter.to.map_or(end, |to| to.min(end)));
+
     let mut next_handle_lock = NEXT_HANDLE
         .lock()
         .map_err(|_| JsValue::from_str("could not acquire next handle"))?;
     let next_handle = next_handle_lock.get_mut();
     let handle: ViewHandle = *next_handle;
     map.insert(handle, new_view);
+    VIEW_FILTERS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not update view filters"))?
+        .insert(handle, filter);
     *next_handle += 1;
 
     Ok(handle)
@@ -223,6 +477,10 @@ pub fn release_view(handle: ViewHandle) -> Result<ViewHandle, JsValue> {
             .map_err(|_| JsValue::from_str("could not acquire views"))?;
 
         map_cell.get_mut().remove(&handle);
+        VIEW_FILTERS
+            .lock()
+            .map_err(|_| JsValue::from_str("could not update view filters"))?
+            .remove(&handle);
 
         Ok(0)
     }
@@ -243,28 +501,18 @@ pub fn get_records(
         .get(&handle)
         .ok_or(JsValue::from_str("no view found for handle"))?;
 
-    let records: Vec<&AdverseEventRecord>;
-
-    let records_ref: &Vec<&AdverseEventRecord> = if start.is_some() || length.is_some() {
-        let mut iter: Box<dyn Iterator<Item = &AdverseEventRecord>> =
-            Box::new(view.records.iter().copied());
-
-        if let Some(start) = start {
-            iter = Box::new(iter.skip(start));
-        }
-
-        if let Some(length) = length {
-            iter = Box::new(iter.take(length));
-        }
+    let visible_attributes = visible_attributes()?;
+    let records = view
+        .redacted_records(profile()?, &visible_attributes)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
 
-        records = iter.collect();
+    let records: Vec<_> = records
+        .into_iter()
+        .skip(start.unwrap_or_default())
+        .take(length.unwrap_or(usize::MAX))
+        .collect();
 
-        &records
-    } else {
-        &view.records
-    };
-
-    serde_json::to_string(records_ref).map_err(|_| JsValue::from_str("failed serializing records"))
+    serde_json::to_string(&records).map_err(|_| JsValue::from_str("failed serializing records"))
 }
 
 #[wasm_bindgen]
@@ -284,11 +532,35 @@ pub fn date_range(handle: ViewHandle) -> Result<String, JsValue> {
         .map_err(|_| JsValue::from_str("failed serializing dates"))
 }
 
+#[wasm_bindgen]
+pub fn get_summary(handle: ViewHandle, suppress_below: Option<usize>) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    match suppress_below {
+        Some(threshold) => {
+            serde_json::to_string(&view.summary().suppressed(&SuppressionRule::new(threshold)))
+        }
+        None => serde_json::to_string(&view.summary()),
+    }
+    .map_err(|_| JsValue::from_str("failed serializing summary"))
+}
+
+/// Always relative to the records in the view. `get_denominated_timeseries`
+/// divides by loaded denominators instead and reports which it used, which
+/// changes the output's shape.
 #[wasm_bindgen]
 pub fn get_timeseries(
     handle: ViewHandle,
     timeseries_type: &str,
     period: &str,
+    suppress_below: Option<usize>,
 ) -> Result<String, JsValue> {
     let mut map_cell = VIEW_MAP
         .lock()
@@ -304,153 +576,666 @@ pub fn get_timeseries(
 
     let period = Period::from_str(period).map_err(|_| JsValue::from_str("invalid period"))?;
 
-    match timeseries_type {
-        TimeseriesType::EventCount => {
-            let view_counts = view.by_period(period, |record| !record.adverse_events.is_empty());
-            let period_counts: Vec<_> = view_counts.into_iter().map(|vc| vc.to_count()).collect();
-            serde_json::to_string(&period_counts)
-                .map_err(|_| JsValue::from_str("failed serializing view counts"))
-        }
-        TimeseriesType::EventPercentage => {
-            let mut with_events: HashMap<(NaiveDate, NaiveDate), DatePeriodView> = view
-                .by_period(period, |record| !record.adverse_events.is_empty())
-                .into_iter()
-                .map(|dpv| ((dpv.start, dpv.end), dpv))
-                .collect();
+    match suppress_below {
+        Some(threshold) => serde_json::to_string(&view.suppressed_timeseries(
+            timeseries_type,
+            period,
+            &SuppressionRule::new(threshold),
+        )),
+        None => serde_json::to_string(&view.timeseries(timeseries_type, period)),
+    }
+    .map_err(|_| JsValue::from_str("failed serializing view counts"))
+}
 
-            let all_records: HashMap<(NaiveDate, NaiveDate), DatePeriodView> = view
-                .by_period(period, |_| true)
-                .into_iter()
-                .map(|dpv| ((dpv.start, dpv.end), dpv))
-                .collect();
+#[wasm_bindgen]
+pub fn get_breakdown(
+    handle: ViewHandle,
+    breakdown_type: &str,
+    stratify_by: Option<String>,
+    suppress_below: Option<usize>,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
 
-            let all_records = sort_map(all_records);
+    let breakdown_type = BreakdownType::from_str(breakdown_type)
+        .map_err(|_| JsValue::from_str("invalid breakdown type"))?;
 
-            let period_percentages: Vec<_> = all_records
-                .into_iter()
-                .map(|(dates, dpv)| {
-                    let with_event_count: usize = with_events
-                        .remove(&dates)
-                        .map(|dpv| dpv.value.records.len())
-                        .unwrap_or_default();
-
-                    let total_count = dpv.value.records.len();
-
-                    DatePeriodPercentage {
-                        period: dpv.period,
-                        start: dpv.start,
-                        end: dpv.end,
-                        value: if total_count == 0 {
-                            0.0
-                        } else {
-                            with_event_count as f64 / total_count as f64 * 100.0
-                        },
-                    }
-                })
-                .collect();
+    let stratify_by = stratify_by
+        .map(|stratify_by| Dimension::from_str(&stratify_by))
+        .transpose()
+        .map_err(|_| JsValue::from_str("invalid stratifying dimension"))?;
+    if let Some(stratify_by) = &stratify_by {
+        profile()?
+            .check_dimension(stratify_by, &visible_attributes()?)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    }
 
-            serde_json::to_string(&period_percentages)
-                .map_err(|_| JsValue::from_str("failed serializing view counts"))
+    match (stratify_by, suppress_below) {
+        (Some(stratify_by), Some(threshold)) => {
+            serde_json::to_string(&view.suppressed_stratified_breakdown(
+                breakdown_type,
+                Some(&stratify_by),
+                &SuppressionRule::new(threshold),
+            ))
         }
-        TimeseriesType::ComplicationSpecifiedCount => {
-            let view_counts = view.by_period(period, |record| record.complications.is_some());
-            let period_counts: Vec<_> = view_counts.into_iter().map(|vc| vc.to_count()).collect();
-            serde_json::to_string(&period_counts)
-                .map_err(|_| JsValue::from_str("failed serializing view counts"))
+        (Some(stratify_by), None) => {
+            serde_json::to_string(&view.get_breakdown(breakdown_type, Some(&stratify_by)))
         }
-        TimeseriesType::ComplicationSpecifiedPercentage => {
-            let mut with_complications: HashMap<(NaiveDate, NaiveDate), DatePeriodView> = view
-                .by_period(period, |record| record.complications.is_some())
+        (None, Some(threshold)) => serde_json::to_string(
+            &view.suppressed_breakdown(breakdown_type, &SuppressionRule::new(threshold)),
+        ),
+        (None, None) => serde_json::to_string(
+            &view
+                .get_breakdown(breakdown_type, None)
                 .into_iter()
-                .map(|dpv| ((dpv.start, dpv.end), dpv))
-                .collect();
+                .flat_map(|stratum| stratum.breakdown)
+                .collect::<Vec<_>>(),
+        ),
+    }
+    .map_err(|_| JsValue::from_str("failed serializing view counts"))
+}
 
-            let all_records: HashMap<(NaiveDate, NaiveDate), DatePeriodView> = view
-                .by_period(period, |_| true)
-                .into_iter()
-                .map(|dpv| ((dpv.start, dpv.end), dpv))
-                .collect();
+#[wasm_bindgen]
+pub fn get_group_counts(
+    handle: ViewHandle,
+    dimension: &str,
+    suppress_below: Option<usize>,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
 
-            let all_records = sort_map(all_records);
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
 
-            let period_percentages: Vec<_> = all_records
+    let dimension =
+        Dimension::from_str(dimension).map_err(|_| JsValue::from_str("invalid dimension"))?;
+
+    profile()?
+        .check_dimension(&dimension, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    match suppress_below {
+        Some(threshold) => serde_json::to_string(
+            &view.suppressed_group_counts(&dimension, &SuppressionRule::new(threshold)),
+        ),
+        None => serde_json::to_string(
+            &view
+                .group_by_dimension(&dimension)
                 .into_iter()
-                .map(|(dates, dpv)| {
-                    let with_complications_count: usize = with_complications
-                        .remove(&dates)
-                        .map(|dpv| dpv.value.records.len())
-                        .unwrap_or_default();
-
-                    let total_count = dpv.value.records.len();
-
-                    DatePeriodPercentage {
-                        period: dpv.period,
-                        start: dpv.start,
-                        end: dpv.end,
-                        value: if total_count == 0 {
-                            0.0
-                        } else {
-                            with_complications_count as f64 / total_count as f64 * 100.0
-                        },
-                    }
+                .map(|group| LabeledCount {
+                    label: group.label,
+                    value: group.value.len(),
                 })
-                .collect();
+                .collect::<Vec<_>>(),
+        ),
+    }
+    .map_err(|_| JsValue::from_str("failed serializing group counts"))
+}
 
-            serde_json::to_string(&period_percentages)
-                .map_err(|_| JsValue::from_str("failed serializing view counts"))
-        }
-        TimeseriesType::ComplicationOccurredCount => {
-            let view_counts = view.by_period(period, |record| record.complications == Some(true));
-            let period_counts: Vec<_> = view_counts.into_iter().map(|vc| vc.to_count()).collect();
-            serde_json::to_string(&period_counts)
-                .map_err(|_| JsValue::from_str("failed serializing view counts"))
+/// Always relative to the records in the view, like `get_timeseries`.
+/// Loaded denominators can't be split into breakdown categories; see
+/// `get_denominated_rates` for rates over them.
+#[wasm_bindgen]
+pub fn get_breakdown_rates(
+    handle: ViewHandle,
+    breakdown_type: &str,
+    outcome: &str,
+    stratify_by: Option<String>,
+    suppress_below: Option<usize>,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let breakdown_type = BreakdownType::from_str(breakdown_type)
+        .map_err(|_| JsValue::from_str("invalid breakdown type"))?;
+    let outcome = Outcome::from_str(outcome).map_err(|_| JsValue::from_str("invalid outcome"))?;
+    profile()?
+        .check_outcome(&outcome, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let stratify_by = stratify_by
+        .map(|stratify_by| Dimension::from_str(&stratify_by))
+        .transpose()
+        .map_err(|_| JsValue::from_str("invalid stratifying dimension"))?;
+    if let Some(stratify_by) = &stratify_by {
+        profile()?
+            .check_dimension(stratify_by, &visible_attributes()?)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    }
+
+    match (stratify_by, suppress_below) {
+        (Some(stratify_by), Some(threshold)) => {
+            serde_json::to_string(&view.suppressed_stratified_breakdown_rates(
+                breakdown_type,
+                Some(&stratify_by),
+                &outcome,
+                &SuppressionRule::new(threshold),
+            ))
         }
-        TimeseriesType::ComplicationOccurredPercentage => {
-            let mut with_complications: HashMap<(NaiveDate, NaiveDate), DatePeriodView> = view
-                .by_period(period, |record| record.complications == Some(true))
-                .into_iter()
-                .map(|dpv| ((dpv.start, dpv.end), dpv))
-                .collect();
+        (Some(stratify_by), None) => serde_json::to_string(&view.get_stratified_breakdown_rates(
+            breakdown_type,
+            Some(&stratify_by),
+            &outcome,
+        )),
+        (None, Some(threshold)) => serde_json::to_string(&suppress_rates(
+            view.get_breakdown_rates(breakdown_type, &outcome),
+            &SuppressionRule::new(threshold),
+        )),
+        (None, None) => serde_json::to_string(&view.get_breakdown_rates(breakdown_type, &outcome)),
+    }
+    .map_err(|_| JsValue::from_str("failed serializing breakdown rates"))
+}
 
-            let all_records: HashMap<(NaiveDate, NaiveDate), DatePeriodView> = view
-                .by_period(period, |_| true)
-                .into_iter()
-                .map(|dpv| ((dpv.start, dpv.end), dpv))
-                .collect();
+#[wasm_bindgen]
+pub fn get_funnel(handle: ViewHandle, dimension: &str, outcome: &str) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
 
-            let all_records = sort_map(all_records);
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
 
-            let period_percentages: Vec<_> = all_records
-                .into_iter()
-                .map(|(dates, dpv)| {
-                    let with_complications_count: usize = with_complications
-                        .remove(&dates)
-                        .map(|dpv| dpv.value.records.len())
-                        .unwrap_or_default();
-
-                    let total_count = dpv.value.records.len();
-
-                    DatePeriodPercentage {
-                        period: dpv.period,
-                        start: dpv.start,
-                        end: dpv.end,
-                        value: if total_count == 0 {
-                            0.0
-                        } else {
-                            with_complications_count as f64 / total_count as f64 * 100.0
-                        },
-                    }
-                })
-                .collect();
+    let dimension =
+        Dimension::from_str(dimension).map_err(|_| JsValue::from_str("invalid dimension"))?;
 
-            serde_json::to_string(&period_percentages)
-                .map_err(|_| JsValue::from_str("failed serializing view counts"))
-        }
+    profile()?
+        .check_dimension(&dimension, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let outcome = Outcome::from_str(outcome).map_err(|_| JsValue::from_str("invalid outcome"))?;
+    profile()?
+        .check_outcome(&outcome, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    serde_json::to_string(&view.funnel(&dimension, outcome))
+        .map_err(|_| JsValue::from_str("failed serializing funnel"))
+}
+
+#[wasm_bindgen]
+pub fn compare(
+    handle_a: ViewHandle,
+    handle_b: ViewHandle,
+    outcome: &str,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let map = map_cell.get_mut();
+    let view_a = map
+        .get(&handle_a)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+    let view_b = map
+        .get(&handle_b)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let outcome = Outcome::from_str(outcome).map_err(|_| JsValue::from_str("invalid outcome"))?;
+    profile()?
+        .check_outcome(&outcome, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    serde_json::to_string(&compare_views(view_a, view_b, outcome))
+        .map_err(|_| JsValue::from_str("failed serializing comparison"))
+}
+
+#[wasm_bindgen]
+pub fn get_cooccurrence(handle: ViewHandle) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    serde_json::to_string(&view.event_cooccurrence())
+        .map_err(|_| JsValue::from_str("failed serializing co-occurrence"))
+}
+
+#[wasm_bindgen]
+pub fn get_event_associations(
+    handle: ViewHandle,
+    event: Option<String>,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let cooccurrence = view.event_cooccurrence();
+    let associations = match event {
+        Some(event) => cooccurrence.associations_given(&event),
+        None => cooccurrence.associations(),
+    };
+
+    serde_json::to_string(&associations)
+        .map_err(|_| JsValue::from_str("failed serializing associations"))
+}
+
+#[wasm_bindgen]
+pub fn get_exposure(handle: ViewHandle, dimension: &str) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let dimension =
+        Dimension::from_str(dimension).map_err(|_| JsValue::from_str("invalid dimension"))?;
+
+    profile()?
+        .check_dimension(&dimension, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    serde_json::to_string(&view.exposure_by(&dimension))
+        .map_err(|_| JsValue::from_str("failed serializing exposure"))
+}
+
+#[wasm_bindgen]
+pub fn get_shrunken_rates(
+    handle: ViewHandle,
+    dimension: &str,
+    outcome: &str,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let dimension =
+        Dimension::from_str(dimension).map_err(|_| JsValue::from_str("invalid dimension"))?;
+
+    profile()?
+        .check_dimension(&dimension, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let outcome = Outcome::from_str(outcome).map_err(|_| JsValue::from_str("invalid outcome"))?;
+    profile()?
+        .check_outcome(&outcome, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    serde_json::to_string(&view.shrunken_rates(&dimension, outcome))
+        .map_err(|_| JsValue::from_str("failed serializing shrunken rates"))
+}
+
+#[wasm_bindgen]
+pub fn get_histogram(
+    handle: ViewHandle,
+    field: &str,
+    binning: &str,
+    outcome: &str,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let field = NumericField::from_str(field).map_err(|_| JsValue::from_str("invalid field"))?;
+
+    profile()?
+        .check_numeric_field(&field, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let binning = Binning::from_str(binning).map_err(|_| JsValue::from_str("invalid binning"))?;
+    let outcome = Outcome::from_str(outcome).map_err(|_| JsValue::from_str("invalid outcome"))?;
+    profile()?
+        .check_outcome(&outcome, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    serde_json::to_string(&view.histogram(&field, &binning, &outcome))
+        .map_err(|_| JsValue::from_str("failed serializing histogram"))
+}
+
+// Rates are standardized to the full data set's case mix
+#[wasm_bindgen]
+pub fn get_standardized_rates(
+    handle: ViewHandle,
+    dimension: &str,
+    outcome: &str,
+    method: &str,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let map = map_cell.get_mut();
+    let base_view = map
+        .get(&0)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+    let view = map
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let dimension =
+        Dimension::from_str(dimension).map_err(|_| JsValue::from_str("invalid dimension"))?;
+
+    profile()?
+        .check_dimension(&dimension, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let outcome = Outcome::from_str(outcome).map_err(|_| JsValue::from_str("invalid outcome"))?;
+    profile()?
+        .check_outcome(&outcome, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let reference = ReferencePopulation::from_view(base_view, outcome);
+    let groups = view.group_by_dimension(&dimension);
+
+    match method {
+        "direct" => serde_json::to_string(&reference.direct_groups(&groups)),
+        "indirect" => serde_json::to_string(&reference.indirect_groups(&groups)),
+        _ => return Err(JsValue::from_str("invalid standardization method")),
     }
+    .map_err(|_| JsValue::from_str("failed serializing standardized rates"))
+}
+
+#[wasm_bindgen]
+pub fn get_staff_rates(
+    handle: ViewHandle,
+    outcome: &str,
+    attribution: &str,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let profile = profile()?;
+    profile
+        .check_query(RecordField::Anesthesiologist)
+        .and_then(|_| profile.check_query(RecordField::AnesthesiaStaff))
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let outcome = Outcome::from_str(outcome).map_err(|_| JsValue::from_str("invalid outcome"))?;
+    profile
+        .check_outcome(&outcome, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let attribution = StaffAttribution::from_str(attribution)
+        .map_err(|_| JsValue::from_str("invalid attribution"))?;
+
+    serde_json::to_string(&view.staff_rates(&outcome, attribution))
+        .map_err(|_| JsValue::from_str("failed serializing staff rates"))
+}
+
+#[wasm_bindgen]
+pub fn get_staff_pairings(
+    handle: ViewHandle,
+    outcome: &str,
+    min_cases: usize,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let profile = profile()?;
+    profile
+        .check_query(RecordField::Anesthesiologist)
+        .and_then(|_| profile.check_query(RecordField::AnesthesiaStaff))
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let outcome = Outcome::from_str(outcome).map_err(|_| JsValue::from_str("invalid outcome"))?;
+    profile
+        .check_outcome(&outcome, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    serde_json::to_string(&view.staff_pairings(&outcome, min_cases))
+        .map_err(|_| JsValue::from_str("failed serializing staff pairings"))
 }
 
 #[wasm_bindgen]
-pub fn get_breakdown(handle: ViewHandle, breakdown_type: &str) -> Result<String, JsValue> {
+pub fn get_patient_timeline(handle: ViewHandle, mrn: &str) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let profile = profile()?;
+    profile
+        .check_query(RecordField::Mrn)
+        .and_then(|_| profile.check_view(RecordField::Mrn))
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let index = view.patient_index();
+    let timeline = index
+        .get(mrn)
+        .ok_or(JsValue::from_str("no patient found for MRN"))?;
+
+    serde_json::to_string(timeline)
+        .map_err(|_| JsValue::from_str("failed serializing patient timeline"))
+}
+
+#[wasm_bindgen]
+pub fn get_repeat_events(
+    handle: ViewHandle,
+    within_days: i32,
+    repeat_match: &str,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let repeat_match = RepeatMatch::from_str(repeat_match)
+        .map_err(|_| JsValue::from_str("invalid repeat match"))?;
+
+    profile()?
+        .check_view(RecordField::Mrn)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    serde_json::to_string(
+        &view
+            .patient_index()
+            .repeat_events(within_days as i64, repeat_match),
+    )
+    .map_err(|_| JsValue::from_str("failed serializing repeat events"))
+}
+
+#[wasm_bindgen]
+pub fn with_prior_event(
+    handle: ViewHandle,
+    within_days: Option<i32>,
+) -> Result<ViewHandle, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not aquire views"))?;
+
+    let map = map_cell.get_mut();
+    let view = map
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    profile()?
+        .check_query(RecordField::Mrn)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let new_view = view.with_prior_event(within_days.map(i64::from));
+    let mut next_handle_lock = NEXT_HANDLE
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire next handle"))?;
+    let next_handle = next_handle_lock.get_mut();
+    let handle: ViewHandle = *next_handle;
+    map.insert(handle, new_view);
+    *next_handle += 1;
+
+    Ok(handle)
+}
+
+#[wasm_bindgen]
+pub fn get_attribute_names(handle: ViewHandle) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let profile = profile()?;
+    let visible_attributes = visible_attributes()?;
+    let names: Vec<&str> = view
+        .attribute_names()
+        .into_iter()
+        .filter(|name| profile.can_use_attribute(name, &visible_attributes))
+        .collect();
+
+    serde_json::to_string(&names)
+        .map_err(|_| JsValue::from_str("failed serializing attribute names"))
+}
+
+#[wasm_bindgen]
+pub fn get_attribute_counts(handle: ViewHandle, name: &str) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    profile()?
+        .check_attribute(name, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    serde_json::to_string(&view.attribute_counts(name))
+        .map_err(|_| JsValue::from_str("failed serializing attribute counts"))
+}
+
+#[wasm_bindgen]
+pub fn export_csv(handle: ViewHandle) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let mut csv = Vec::new();
+    view.write_redacted_csv(&mut csv, profile()?, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    String::from_utf8(csv).map_err(|_| JsValue::from_str("exported CSV is not valid UTF-8"))
+}
+
+/// Sets the true case volumes used by the `get_denominated_*` functions.
+/// They're limited to the dates of any `between` a view was made with, or
+/// cover their full range otherwise. Views filtered any other way are
+/// refused, since denominators can't follow those filters.
+#[wasm_bindgen]
+pub fn load_denominators(data: &[u8]) -> Result<(), JsValue> {
+    let denominators = Denominators::from_csv_reader(Cursor::new(data))
+        .map_err(|e| JsValue::from_str(&format!("{:?}", e)))?;
+
+    let records_cell = RECORDS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not update records"))?;
+    unsafe {
+        (*records_cell.get()).set_denominators(denominators);
+    }
+
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn get_denominated_timeseries(
+    handle: ViewHandle,
+    timeseries_type: &str,
+    period: &str,
+    suppress_below: Option<usize>,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let timeseries_type = TimeseriesType::from_str(timeseries_type)
+        .map_err(|_| JsValue::from_str("invalid timeseries type"))?;
+    let period = Period::from_str(period).map_err(|_| JsValue::from_str("invalid period"))?;
+
+    let records_cell = RECORDS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire records"))?;
+    let denominators =
+        unsafe { (*records_cell.get()).view_denominators(view, &view_filter(handle)?) }
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let timeseries = view.denominated_timeseries(timeseries_type, period, denominators.as_ref());
+    match suppress_below {
+        Some(threshold) => serde_json::to_string(&Denominated {
+            denominator: timeseries.denominator,
+            value: view.suppress_timeseries(
+                timeseries.value,
+                timeseries_type,
+                period,
+                &SuppressionRule::new(threshold),
+            ),
+        }),
+        None => serde_json::to_string(&timeseries),
+    }
+    .map_err(|_| JsValue::from_str("failed serializing timeseries"))
+}
+
+/// Like `get_breakdown_rates`, but refused once denominators are loaded.
+#[wasm_bindgen]
+pub fn get_denominated_breakdown_rates(
+    handle: ViewHandle,
+    breakdown_type: &str,
+    outcome: &str,
+    suppress_below: Option<usize>,
+) -> Result<String, JsValue> {
     let mut map_cell = VIEW_MAP
         .lock()
         .map_err(|_| JsValue::from_str("could not acquire views"))?;
@@ -462,9 +1247,77 @@ pub fn get_breakdown(handle: ViewHandle, breakdown_type: &str) -> Result<String,
 
     let breakdown_type = BreakdownType::from_str(breakdown_type)
         .map_err(|_| JsValue::from_str("invalid breakdown type"))?;
+    let outcome = Outcome::from_str(outcome).map_err(|_| JsValue::from_str("invalid outcome"))?;
+    profile()?
+        .check_outcome(&outcome, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let records_cell = RECORDS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire records"))?;
+    let denominators =
+        unsafe { (*records_cell.get()).view_denominators(view, &view_filter(handle)?) }
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let rates = view
+        .denominated_breakdown_rates(breakdown_type, &outcome, denominators.as_ref())
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    match suppress_below {
+        Some(threshold) => serde_json::to_string(&Denominated {
+            denominator: rates.denominator,
+            value: suppress_rates(rates.value, &SuppressionRule::new(threshold)),
+        }),
+        None => serde_json::to_string(&rates),
+    }
+    .map_err(|_| JsValue::from_str("failed serializing breakdown rates"))
+}
 
-    serde_json::to_string(&view.get_breakdown(breakdown_type))
-        .map_err(|_| JsValue::from_str("failed serializing view counts"))
+#[wasm_bindgen]
+pub fn get_denominated_rates(
+    handle: ViewHandle,
+    dimension: &str,
+    outcome: &str,
+    suppress_below: Option<usize>,
+) -> Result<String, JsValue> {
+    let mut map_cell = VIEW_MAP
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire views"))?;
+
+    let view = map_cell
+        .get_mut()
+        .get(&handle)
+        .ok_or(JsValue::from_str("no view found for handle"))?;
+
+    let dimension =
+        Dimension::from_str(dimension).map_err(|_| JsValue::from_str("invalid dimension"))?;
+
+    profile()?
+        .check_dimension(&dimension, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let outcome = Outcome::from_str(outcome).map_err(|_| JsValue::from_str("invalid outcome"))?;
+    profile()?
+        .check_outcome(&outcome, &visible_attributes()?)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let records_cell = RECORDS
+        .lock()
+        .map_err(|_| JsValue::from_str("could not acquire records"))?;
+    let denominators =
+        unsafe { (*records_cell.get()).view_denominators(view, &view_filter(handle)?) }
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let rates = view
+        .denominated_rates(&dimension, &outcome, denominators.as_ref())
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    match suppress_below {
+        Some(threshold) => serde_json::to_string(&Denominated {
+            denominator: rates.denominator,
+            value: suppress_rates(rates.value, &SuppressionRule::new(threshold)),
+        }),
+        None => serde_json::to_string(&rates),
+    }
+    .map_err(|_| JsValue::from_str("failed serializing rates"))
 }
 
 #[derive(Debug)]